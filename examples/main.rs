@@ -42,7 +42,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     dbg!(conn.pause_tube("jobs", time::Duration::from_secs(10)))?;
     dbg!(conn.pause_tube("not-found", time::Duration::from_secs(10)))?;
 
-    let mut job = conn.reserve()?;
+    let mut job = conn.reserve()?.into_job()?;
     dbg!(job.id());
     dbg!(std::str::from_utf8(job.body()))?;
     dbg!(job.reserved());
@@ -54,7 +54,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     dbg!(job.release_default())?;
     dbg!(job.delete())?;
 
-    let mut job = conn.reserve()?;
+    let mut job = conn.reserve()?.into_job()?;
     dbg!(job.delete())?;
 
     // should also work with potentially non-UTF-8 payloads
@@ -65,7 +65,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     dbg!(conn.put_default(&buf))?;
 
     // tries to read the gzipped encoded message back to a string
-    let mut job = conn.reserve()?;
+    let mut job = conn.reserve()?.into_job()?;
     let mut buf = &job.body().to_owned()[..];
     let mut gz = GzDecoder::new(&mut buf);
     let mut s = String::new();
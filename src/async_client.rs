@@ -0,0 +1,638 @@
+use std::io;
+use std::time::Duration;
+
+use futures::{SinkExt, StreamExt};
+use tokio::net::{self, TcpStream};
+use tokio::time;
+use tokio_util::codec::Framed;
+
+use crate::codec::Codec;
+use crate::command::{self, Command, Status};
+use crate::config::*;
+use crate::error::{BeanstalkcError, BeanstalkcResult};
+use crate::job::{Outcome, OwnedJob};
+use crate::job_stream::JobStream;
+use crate::response::Response;
+use crate::stats::{JobStats, ServerStats, TubeStats};
+
+/// `AsyncBeanstalkc` provides beanstalkd client operations on top of tokio.
+///
+/// It mirrors `Beanstalkc`, except that jobs are returned as `OwnedJob`s, and that
+/// the connection must be closed explicitly with `quit` since it cannot be done on
+/// drop.
+///
+/// Its methods are cancel safe as far as the connection goes: when a future is
+/// dropped after its command was sent, the reply is skipped by the next command.
+/// A job reserved by a dropped `reserve` stays reserved until its TTR expires.
+///
+/// # Example
+///
+/// ```no_run
+/// # async fn run() -> beanstalkc::BeanstalkcResult<()> {
+/// use beanstalkc::AsyncBeanstalkc;
+///
+/// let mut conn = AsyncBeanstalkc::new().connect().await?;
+///
+/// conn.put_default(b"Rust").await?;
+/// let job = conn.reserve().await?.into_job()?;
+/// conn.delete(job.id()).await?;
+/// conn.quit().await
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct AsyncBeanstalkc {
+    host: String,
+    port: u16,
+    connection_timeout: Option<Duration>,
+    stream: Option<Framed<TcpStream, Codec>>,
+    // The replies to commands sent by futures dropped before reading them.
+    pending_replies: usize,
+}
+
+impl AsyncBeanstalkc {
+    /// Create a new `AsyncBeanstalkc` instance with default configs.
+    /// Default connection address is `localhost:11300`
+    pub fn new() -> AsyncBeanstalkc {
+        AsyncBeanstalkc {
+            host: DEFAULT_HOST.to_string(),
+            port: DEFAULT_PORT,
+            connection_timeout: DEFAULT_CONNECTION_TIMEOUT,
+            stream: None,
+            pending_replies: 0,
+        }
+    }
+
+    /// Change host to beanstalkd server.
+    pub fn host(mut self, host: &str) -> Self {
+        self.host = host.to_string();
+        self
+    }
+
+    /// Change port to beanstalkd server.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Set timeout for TCP connection to beanstalkd server.
+    /// Default connection timeout is `120s`.
+    pub fn connection_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.connection_timeout = timeout;
+        self
+    }
+
+    /// Connect to a running beanstalkd server, trying every resolved address.
+    pub async fn connect(mut self) -> BeanstalkcResult<Self> {
+        let addr = format!("{}:{}", self.host, self.port);
+        let addresses: Vec<_> = net::lookup_host((self.host.as_str(), self.port))
+            .await
+            .map_err(|e| {
+                BeanstalkcError::ConnectionError(format!("failed to resolve {}: {}", addr, e))
+            })?
+            .collect();
+        if addresses.is_empty() {
+            return Err(BeanstalkcError::ConnectionError(format!(
+                "no addresses found for {}",
+                addr
+            )));
+        }
+
+        let mut failures = vec![];
+        for address in addresses {
+            let result = match self.connection_timeout {
+                Some(timeout) => match time::timeout(timeout, TcpStream::connect(address)).await {
+                    Ok(result) => result,
+                    Err(_) => Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "connection timed out",
+                    )),
+                },
+                None => TcpStream::connect(address).await,
+            };
+            match result {
+                Ok(tcp_stream) => {
+                    self.stream = Some(Framed::new(tcp_stream, Codec));
+                    return Ok(self);
+                }
+                Err(e) => failures.push(format!("{}: {}", address, e)),
+            }
+        }
+
+        Err(BeanstalkcError::ConnectionError(format!(
+            "failed to connect to {} ({})",
+            addr,
+            failures.join(", ")
+        )))
+    }
+
+    /// Close the connection to the server.
+    pub async fn quit(mut self) -> BeanstalkcResult<()> {
+        if let Some(mut stream) = self.stream.take() {
+            stream.send(command::quit().build()).await?;
+        }
+        Ok(())
+    }
+
+    /// Put a job into the current tube with default configs. Return job id.
+    pub async fn put_default(&mut self, body: &[u8]) -> BeanstalkcResult<u64> {
+        self.put(
+            body,
+            DEFAULT_JOB_PRIORITY,
+            DEFAULT_JOB_DELAY,
+            DEFAULT_JOB_TTR,
+        )
+        .await
+    }
+
+    /// Put a job into the current tube and return the job id.
+    ///
+    /// A job buried because the server ran out of memory is reported as
+    /// `BeanstalkcError::Buried`, like `Beanstalkc::put` does.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # async fn run() -> beanstalkc::BeanstalkcResult<()> {
+    /// use std::time::Duration;
+    /// use beanstalkc::AsyncBeanstalkc;
+    ///
+    /// let mut conn = AsyncBeanstalkc::new().connect().await?;
+    ///
+    /// let job_id = conn
+    ///     .put(b"Rust", 0, Duration::from_secs(1), Duration::from_secs(10))
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn put(
+        &mut self,
+        body: &[u8],
+        priority: u32,
+        delay: Duration,
+        ttr: Duration,
+    ) -> BeanstalkcResult<u64> {
+        self.send(command::put(body, priority, delay, ttr))
+            .await?
+            .job_id()
+    }
+
+    /// Reserve a job from one of those watched tubes.
+    ///
+    /// Return `AsyncReserveOutcome::Job` if it succeeds, or
+    /// `AsyncReserveOutcome::DeadlineSoon` if a job reserved by this client is about
+    /// to exceed its TTR.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # async fn run() -> beanstalkc::BeanstalkcResult<()> {
+    /// use beanstalkc::{AsyncBeanstalkc, AsyncReserveOutcome};
+    ///
+    /// let mut conn = AsyncBeanstalkc::new().connect().await?;
+    ///
+    /// match conn.reserve().await? {
+    ///     AsyncReserveOutcome::Job(job) => {
+    ///         // Execute job...
+    ///         conn.delete(job.id()).await?;
+    ///     }
+    ///     AsyncReserveOutcome::DeadlineSoon => {
+    ///         // Touch, release or delete in-flight jobs...
+    ///     }
+    ///     AsyncReserveOutcome::TimedOut => unreachable!(),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn reserve(&mut self) -> BeanstalkcResult<AsyncReserveOutcome> {
+        let resp = self.send(command::reserve(None)).await?;
+        reserve_outcome(resp)
+    }
+
+    /// Reserve a job with given timeout from one of those watched tubes.
+    ///
+    /// Return `AsyncReserveOutcome::Job` if it succeeds, `AsyncReserveOutcome::TimedOut`
+    /// if no job became available in time, or `AsyncReserveOutcome::DeadlineSoon` if a
+    /// job reserved by this client is about to exceed its TTR.
+    pub async fn reserve_with_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> BeanstalkcResult<AsyncReserveOutcome> {
+        let resp = self.send(command::reserve(Some(timeout))).await?;
+        reserve_outcome(resp)
+    }
+
+    /// Reserve a specific job by its id, whatever state it is in.
+    pub async fn reserve_job(&mut self, job_id: u64) -> BeanstalkcResult<OwnedJob> {
+        let resp = self.send(command::reserve_job(job_id)).await?;
+        reserve_outcome(resp)?.into_job()
+    }
+
+    /// Kick at most `bound` jobs into the ready queue from buried or delayed queues.
+    pub async fn kick(&mut self, bound: u32) -> BeanstalkcResult<u64> {
+        self.send(command::kick(bound)).await?.get_int_param(0)
+    }
+
+    /// Kick a specific job into the ready queue.
+    pub async fn kick_job(&mut self, job_id: u64) -> BeanstalkcResult<()> {
+        self.send(command::kick_job(job_id)).await.map(|_| ())
+    }
+
+    /// Return a specific job.
+    pub async fn peek(&mut self, job_id: u64) -> BeanstalkcResult<OwnedJob> {
+        self.do_peek(command::peek_job(job_id)).await
+    }
+
+    /// Return the next ready job.
+    pub async fn peek_ready(&mut self) -> BeanstalkcResult<OwnedJob> {
+        self.do_peek(command::peek_ready()).await
+    }
+
+    /// Return the delayed job with the shortest delay left.
+    pub async fn peek_delayed(&mut self) -> BeanstalkcResult<OwnedJob> {
+        self.do_peek(command::peek_delayed()).await
+    }
+
+    /// Return the next job in the list of buried jobs.
+    pub async fn peek_buried(&mut self) -> BeanstalkcResult<OwnedJob> {
+        self.do_peek(command::peek_buried()).await
+    }
+
+    async fn do_peek(&mut self, cmd: Command<'_>) -> BeanstalkcResult<OwnedJob> {
+        let resp = self.send(cmd).await?;
+        Ok(OwnedJob::new(
+            resp.job_id()?,
+            resp.body.unwrap_or_default(),
+            false,
+        ))
+    }
+
+    /// Return a list of all existing tubes.
+    pub async fn tubes(&mut self) -> BeanstalkcResult<Vec<String>> {
+        self.send(command::tubes()).await?.body_as_vec()
+    }
+
+    /// Return the tube currently being used.
+    pub async fn using(&mut self) -> BeanstalkcResult<String> {
+        self.send(command::using()).await?.get_param(0)
+    }
+
+    /// Use a given tube.
+    pub async fn use_tube(&mut self, name: &str) -> BeanstalkcResult<String> {
+        self.send(command::use_tube(name)).await?.get_param(0)
+    }
+
+    /// Return a list of tubes currently being watched.
+    pub async fn watching(&mut self) -> BeanstalkcResult<Vec<String>> {
+        self.send(command::watching()).await?.body_as_vec()
+    }
+
+    /// Watch a specific tube.
+    pub async fn watch(&mut self, name: &str) -> BeanstalkcResult<u64> {
+        self.send(command::watch(name)).await?.get_int_param(0)
+    }
+
+    /// Stop watching a specific tube.
+    pub async fn ignore(&mut self, name: &str) -> BeanstalkcResult<u64> {
+        self.send(command::ignore(name)).await?.get_int_param(0)
+    }
+
+    /// Return statistical information about the beanstalkd server.
+    pub async fn stats(&mut self) -> BeanstalkcResult<ServerStats> {
+        self.send(command::stats()).await?.body_as()
+    }
+
+    /// Return statistical information about the specified tube.
+    pub async fn stats_tube(&mut self, name: &str) -> BeanstalkcResult<TubeStats> {
+        self.send(command::stats_tube(name)).await?.body_as()
+    }
+
+    /// Delay any new job being reserved from the given tube.
+    pub async fn pause_tube(&mut self, name: &str, delay: Duration) -> BeanstalkcResult<()> {
+        self.send(command::pause_tube(name, delay))
+            .await
+            .map(|_| ())
+    }
+
+    /// Delete a job by its id.
+    pub async fn delete(&mut self, job_id: u64) -> BeanstalkcResult<()> {
+        self.send(command::delete(job_id)).await.map(|_| ())
+    }
+
+    /// Release a reserved job back into the ready queue with default priority and delay.
+    pub async fn release_default(&mut self, job_id: u64) -> BeanstalkcResult<()> {
+        self.release(job_id, DEFAULT_JOB_PRIORITY, DEFAULT_JOB_DELAY)
+            .await
+    }
+
+    /// Release a reserved job back into the ready queue.
+    pub async fn release(
+        &mut self,
+        job_id: u64,
+        priority: u32,
+        delay: Duration,
+    ) -> BeanstalkcResult<()> {
+        self.send(command::release(job_id, priority, delay))
+            .await
+            .map(|_| ())
+    }
+
+    /// Bury a job by its id with default priority.
+    pub async fn bury_default(&mut self, job_id: u64) -> BeanstalkcResult<()> {
+        self.bury(job_id, DEFAULT_JOB_PRIORITY).await
+    }
+
+    /// Bury a job by its id.
+    pub async fn bury(&mut self, job_id: u64, priority: u32) -> BeanstalkcResult<()> {
+        self.send(command::bury(job_id, priority)).await.map(|_| ())
+    }
+
+    /// Touch a job by its id, requesting more time to work on it.
+    pub async fn touch(&mut self, job_id: u64) -> BeanstalkcResult<()> {
+        self.send(command::touch(job_id)).await.map(|_| ())
+    }
+
+    /// Return statistical information about a job.
+    pub async fn stats_job(&mut self, job_id: u64) -> BeanstalkcResult<JobStats> {
+        self.send(command::stats_job(job_id)).await?.body_as()
+    }
+
+    /// Watch the given tubes only and turn this connection into a stream of the
+    /// jobs reserved from them.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # async fn run() -> beanstalkc::BeanstalkcResult<()> {
+    /// use beanstalkc::AsyncBeanstalkc;
+    /// use futures::StreamExt;
+    ///
+    /// let conn = AsyncBeanstalkc::new().connect().await?;
+    /// let mut jobs = conn.jobs(&["a", "b"]).await?;
+    ///
+    /// while let Some(job) = jobs.next().await {
+    ///     let job = job?;
+    ///     // Execute job...
+    ///     jobs.connection().unwrap().delete(job.id()).await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tubes` is empty.
+    pub async fn jobs(mut self, tubes: &[&str]) -> BeanstalkcResult<JobStream> {
+        assert!(!tubes.is_empty(), "job stream needs at least one tube");
+        for tube in tubes {
+            self.watch(tube).await?;
+        }
+        for tube in self.watching().await? {
+            if !tubes.contains(&tube.as_str()) {
+                self.ignore(&tube).await?;
+            }
+        }
+        Ok(JobStream::new(self))
+    }
+
+    /// Delete, release or bury a reserved job according to `outcome`.
+    pub async fn finish(&mut self, job: OwnedJob, outcome: Outcome) -> BeanstalkcResult<()> {
+        match outcome {
+            Outcome::Delete => self.delete(job.id()).await,
+            Outcome::Release { priority, delay } => self.release(job.id(), priority, delay).await,
+            Outcome::Bury { priority } => self.bury(job.id(), priority).await,
+        }
+    }
+
+    async fn send(&mut self, cmd: Command<'_>) -> BeanstalkcResult<Response> {
+        let stream = match self.stream.as_mut() {
+            Some(stream) => stream,
+            None => {
+                return Err(BeanstalkcError::ConnectionError(
+                    "invalid connection".to_string(),
+                ))
+            }
+        };
+
+        // Skip the replies left over by cancelled commands first.
+        stream.flush().await?;
+        while self.pending_replies > 0 {
+            next_reply(stream).await?;
+            self.pending_replies -= 1;
+        }
+
+        stream.feed(cmd.build()).await?;
+        self.pending_replies += 1;
+        stream.flush().await?;
+        let resp = next_reply(stream).await?;
+        self.pending_replies -= 1;
+        cmd.check(resp)
+    }
+}
+
+impl Default for AsyncBeanstalkc {
+    fn default() -> Self {
+        AsyncBeanstalkc::new()
+    }
+}
+
+/// `AsyncReserveOutcome` is what the server answered to a reserve command sent by
+/// an `AsyncBeanstalkc`.
+#[derive(Debug)]
+pub enum AsyncReserveOutcome {
+    /// A job was reserved.
+    Job(OwnedJob),
+    /// A job reserved by this client is about to exceed its TTR. Delete, release or
+    /// touch it before reserving again.
+    DeadlineSoon,
+    /// No job became available before the reserve timeout expired.
+    TimedOut,
+}
+
+impl AsyncReserveOutcome {
+    /// Return the reserved job, if any.
+    pub fn job(self) -> Option<OwnedJob> {
+        match self {
+            AsyncReserveOutcome::Job(job) => Some(job),
+            _ => None,
+        }
+    }
+
+    /// Return the reserved job, turning `DeadlineSoon` and `TimedOut` into errors.
+    pub fn into_job(self) -> BeanstalkcResult<OwnedJob> {
+        match self {
+            AsyncReserveOutcome::Job(job) => Ok(job),
+            AsyncReserveOutcome::DeadlineSoon => Err(BeanstalkcError::DeadlineSoon),
+            AsyncReserveOutcome::TimedOut => Err(BeanstalkcError::TimedOut),
+        }
+    }
+}
+
+fn reserve_outcome(resp: Response) -> BeanstalkcResult<AsyncReserveOutcome> {
+    match resp.status {
+        Status::DeadlineSoon => Ok(AsyncReserveOutcome::DeadlineSoon),
+        Status::TimedOut => Ok(AsyncReserveOutcome::TimedOut),
+        _ => Ok(AsyncReserveOutcome::Job(OwnedJob::new(
+            resp.job_id()?,
+            resp.body.unwrap_or_default(),
+            true,
+        ))),
+    }
+}
+
+async fn next_reply(stream: &mut Framed<TcpStream, Codec>) -> BeanstalkcResult<Response> {
+    match stream.next().await {
+        Some(resp) => Ok(resp?),
+        None => {
+            Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed by server").into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockServer;
+    use crate::stats::JobState;
+
+    #[tokio::test]
+    async fn test_put_reserve_delete() {
+        let server = MockServer::start();
+        let mut conn = server.connect_async().await;
+
+        conn.use_tube("jobs").await.unwrap();
+        conn.watch("jobs").await.unwrap();
+        conn.ignore("default").await.unwrap();
+        assert_eq!(vec!["jobs".to_string()], conn.watching().await.unwrap());
+
+        let body = b"binary\r\n\0body";
+        let id = conn.put_default(body).await.unwrap();
+        let job = conn.reserve().await.unwrap().into_job().unwrap();
+        assert_eq!(id, job.id());
+        assert_eq!(&body[..], job.body());
+        assert!(job.reserved());
+
+        let stats = conn.stats_job(id).await.unwrap();
+        assert_eq!(JobState::Reserved, stats.state);
+        assert_eq!("jobs", stats.tube);
+
+        conn.finish(job, Outcome::Delete).await.unwrap();
+        assert!(matches!(
+            conn.peek(id).await,
+            Err(BeanstalkcError::NotFound)
+        ));
+        conn.quit().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reserve_timed_out() {
+        let server = MockServer::start();
+        let mut conn = server.connect_async().await;
+
+        let outcome = conn
+            .reserve_with_timeout(Duration::from_secs(0))
+            .await
+            .unwrap();
+        assert!(matches!(outcome, AsyncReserveOutcome::TimedOut));
+        assert!(matches!(outcome.into_job(), Err(BeanstalkcError::TimedOut)));
+    }
+
+    #[tokio::test]
+    async fn test_reserve_deadline_soon() {
+        let server = MockServer::start();
+        let mut conn = server.connect_async().await;
+
+        let id = conn
+            .put(b"job", 0, Duration::from_secs(0), Duration::from_secs(1))
+            .await
+            .unwrap();
+        conn.reserve().await.unwrap().into_job().unwrap();
+
+        let outcome = conn.reserve().await.unwrap();
+        assert!(matches!(outcome, AsyncReserveOutcome::DeadlineSoon));
+        conn.delete(id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_command_keeps_connection_in_sync() {
+        let server = MockServer::start();
+        let mut conn = server.connect_async().await;
+
+        // The reserve is sent, but dropped before any job is available.
+        assert!(time::timeout(
+            Duration::from_millis(50),
+            conn.reserve_with_timeout(Duration::from_secs(1))
+        )
+        .await
+        .is_err());
+        let id = conn.put_default(b"job").await.unwrap();
+        assert_eq!(id, conn.peek_ready().await.unwrap().id());
+        assert_eq!("default", conn.using().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_release_and_bury() {
+        let server = MockServer::start();
+        let mut conn = server.connect_async().await;
+
+        let id = conn.put_default(b"job").await.unwrap();
+        conn.reserve().await.unwrap();
+        conn.release(id, 10, Duration::from_secs(0)).await.unwrap();
+        assert_eq!(b"job", conn.peek_ready().await.unwrap().body());
+
+        conn.reserve().await.unwrap();
+        conn.bury_default(id).await.unwrap();
+        assert_eq!(id, conn.peek_buried().await.unwrap().id());
+        assert_eq!(1, conn.kick(10).await.unwrap());
+        assert_eq!(
+            1,
+            conn.stats_tube("default").await.unwrap().current_jobs_ready
+        );
+    }
+
+    #[tokio::test]
+    async fn test_put_buried() {
+        let server = MockServer::start();
+        let mut conn = server.connect_async().await;
+
+        server.bury_puts(true);
+        match conn.put_default(b"job").await {
+            Err(BeanstalkcError::Buried(id)) => conn.kick_job(id).await.unwrap(),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_jobs_watch_given_tubes_only() {
+        let server = MockServer::start();
+        let mut conn = server.connect_async().await;
+        conn.watch("previous").await.unwrap();
+
+        let mut jobs = conn.jobs(&["a"]).await.unwrap();
+        assert_eq!(
+            vec!["a".to_string()],
+            jobs.connection().unwrap().watching().await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "at least one tube")]
+    async fn test_jobs_without_tubes() {
+        let server = MockServer::start();
+        let _ = server.connect_async().await.jobs(&[]).await;
+    }
+
+    #[tokio::test]
+    async fn test_connect_refused() {
+        let port = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port()
+        };
+
+        let result = AsyncBeanstalkc::new()
+            .host("127.0.0.1")
+            .port(port)
+            .connect()
+            .await;
+        assert!(matches!(result, Err(BeanstalkcError::ConnectionError(_))));
+    }
+}
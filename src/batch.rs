@@ -0,0 +1,290 @@
+use std::time::Duration;
+
+use crate::config::*;
+use crate::error::{BeanstalkcError, BeanstalkcResult};
+use crate::pipeline::Reply;
+use crate::Beanstalkc;
+
+/// `PutJob` describes one job of `Beanstalkc::put_many`.
+///
+/// It can be built from a `(body, priority, delay, ttr)` tuple, which puts the job
+/// into the current tube.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use beanstalkc::PutJob;
+///
+/// let job = PutJob::new(b"Rust")
+///     .priority(10)
+///     .delay(Duration::from_secs(5))
+///     .tube("jobs");
+/// let job = PutJob::from((&b"Rust"[..], 10, Duration::from_secs(5), Duration::from_secs(60)));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PutJob<'a> {
+    body: &'a [u8],
+    priority: u32,
+    delay: Duration,
+    ttr: Duration,
+    tube: Option<&'a str>,
+}
+
+impl<'a> PutJob<'a> {
+    /// Create a job with default configs, put into the current tube.
+    pub fn new(body: &'a [u8]) -> PutJob<'a> {
+        PutJob {
+            body,
+            priority: DEFAULT_JOB_PRIORITY,
+            delay: DEFAULT_JOB_DELAY,
+            ttr: DEFAULT_JOB_TTR,
+            tube: None,
+        }
+    }
+
+    /// Set the job priority. Default is `0`.
+    pub fn priority(mut self, priority: u32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Set the job delay. Default is `0s`.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Set the job TTR. Default is `120s`.
+    pub fn ttr(mut self, ttr: Duration) -> Self {
+        self.ttr = ttr;
+        self
+    }
+
+    /// Put the job into the given tube instead of the current one.
+    pub fn tube(mut self, name: &'a str) -> Self {
+        self.tube = Some(name);
+        self
+    }
+}
+
+impl<'a> From<(&'a [u8], u32, Duration, Duration)> for PutJob<'a> {
+    fn from((body, priority, delay, ttr): (&'a [u8], u32, Duration, Duration)) -> Self {
+        PutJob::new(body).priority(priority).delay(delay).ttr(ttr)
+    }
+}
+
+/// Put the jobs through a single pipeline, then use the original tube again.
+///
+/// A job whose `use` failed is deleted from the tube it was put into instead, and
+/// gets the error of that `use`, wrapped in `Misplaced` if the job could not be
+/// deleted.
+pub(crate) fn put_many<'a, I>(conn: &mut Beanstalkc, jobs: I) -> Vec<BeanstalkcResult<u64>>
+where
+    I: IntoIterator,
+    I::Item: Into<PutJob<'a>>,
+{
+    let original = conn.current_tube().to_string();
+    let jobs: Vec<PutJob> = jobs.into_iter().map(Into::into).collect();
+
+    let mut current = original.as_str();
+    let mut is_use = Vec::with_capacity(jobs.len());
+    let mut pipeline = conn.pipeline();
+    for job in &jobs {
+        let tube = job.tube.unwrap_or(original.as_str());
+        if tube != current {
+            pipeline = pipeline.use_tube(tube);
+            is_use.push(true);
+            current = tube;
+        }
+        pipeline = pipeline.put(job.body, job.priority, job.delay, job.ttr);
+        is_use.push(false);
+    }
+
+    let mut results = Vec::with_capacity(jobs.len());
+    // Jobs put into the wrong tube because their `use` failed, with their index.
+    let mut misplaced = vec![];
+    let mut use_error = None;
+    for (reply, is_use) in pipeline.execute().into_iter().zip(is_use) {
+        if is_use {
+            use_error = reply.err();
+            continue;
+        }
+
+        let result = reply.map(|reply| match reply {
+            Reply::Inserted(id) => id,
+            _ => unreachable!("put is answered by INSERTED"),
+        });
+        match (use_error.clone(), result) {
+            (Some(e), Ok(id)) | (Some(e), Err(BeanstalkcError::Buried(id))) => {
+                misplaced.push((results.len(), id));
+                results.push(Err(e));
+            }
+            (Some(e), Err(_)) => results.push(Err(e)),
+            (None, result) => results.push(result),
+        }
+    }
+
+    for (index, id) in misplaced {
+        if conn.delete(id).is_err() {
+            if let Err(e) = &results[index] {
+                results[index] = Err(BeanstalkcError::Misplaced(id, Box::new(e.clone())));
+            }
+        }
+    }
+    if conn.current_tube() != original && conn.use_tube(&original).is_err() {
+        // Make the next command fail, or reconnect into the original tube, rather
+        // than put its jobs into the wrong one.
+        conn.record_use(&original);
+        conn.close();
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockServer;
+
+    #[test]
+    fn test_put_many() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+        server.max_job_size(65535);
+
+        let big = vec![b'x'; 65536];
+        let jobs = vec![
+            (
+                &b"first"[..],
+                5,
+                Duration::from_secs(0),
+                Duration::from_secs(60),
+            ),
+            (&big[..], 0, Duration::from_secs(0), Duration::from_secs(60)),
+            (
+                &b"second"[..],
+                7,
+                Duration::from_secs(10),
+                Duration::from_secs(30),
+            ),
+        ];
+        let results = conn.put_many(jobs);
+        assert_eq!(3, results.len());
+        assert_eq!(1, *results[0].as_ref().unwrap());
+        assert!(matches!(results[1], Err(BeanstalkcError::JobTooBig)));
+        assert_eq!(2, *results[2].as_ref().unwrap());
+
+        let stats = conn.stats_job(2).unwrap();
+        assert_eq!(7, stats.pri);
+        assert_eq!(Duration::from_secs(30), stats.ttr);
+        assert!(conn.put_many(Vec::<PutJob>::new()).is_empty());
+    }
+
+    #[test]
+    fn test_put_many_chunks() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        let count = 1000;
+        let results = conn.put_many((0..count).map(|_| PutJob::new(b"job")));
+        let ids: Vec<u64> = results.into_iter().map(Result::unwrap).collect();
+        assert_eq!((1..=count as u64).collect::<Vec<_>>(), ids);
+    }
+
+    #[test]
+    fn test_put_many_tubes() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+        conn.use_tube("original").unwrap();
+
+        let jobs = vec![
+            PutJob::new(b"a").tube("a"),
+            PutJob::new(b"b").tube("b"),
+            PutJob::new(b"original"),
+            PutJob::new(b"b").tube("b"),
+        ];
+        let ids: Vec<u64> = conn
+            .put_many(jobs)
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        let tubes: Vec<String> = ids
+            .iter()
+            .map(|id| conn.stats_job(*id).unwrap().tube)
+            .collect();
+        assert_eq!(vec!["a", "b", "original", "b"], tubes);
+        assert_eq!("original", conn.using().unwrap());
+    }
+
+    #[test]
+    fn test_put_many_use_failure() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        let results = conn.put_many(vec![
+            PutJob::new(b"invalid").tube(""),
+            PutJob::new(b"valid").tube("jobs"),
+        ]);
+        assert!(matches!(
+            results[0],
+            Err(BeanstalkcError::UnexpectedResponse(_))
+        ));
+        assert!(conn.peek(1).is_err());
+        let id = *results[1].as_ref().unwrap();
+        assert_eq!("jobs", conn.stats_job(id).unwrap().tube);
+        assert_eq!("default", conn.using().unwrap());
+    }
+
+    #[test]
+    fn test_put_many_misplaced_delete_failure() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+        conn.use_tube("original").unwrap();
+
+        server.reject_deletes(true);
+        let results = conn.put_many(vec![
+            PutJob::new(b"valid").tube("jobs"),
+            PutJob::new(b"invalid").tube(""),
+            PutJob::new(b"valid").tube("jobs"),
+        ]);
+        assert_eq!(3, results.len());
+        assert!(results[0].is_ok());
+        match &results[1] {
+            Err(BeanstalkcError::Misplaced(id, e)) => {
+                assert_eq!("jobs", conn.stats_job(*id).unwrap().tube);
+                assert!(matches!(**e, BeanstalkcError::UnexpectedResponse(_)));
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert!(results[2].is_ok());
+        assert_eq!("original", conn.using().unwrap());
+    }
+
+    #[test]
+    fn test_put_many_connection_failure() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        server.hang_up_after(300);
+        let results = conn.put_many((0..1000).map(|_| PutJob::new(b"job")));
+        assert_eq!(1000, results.len());
+        assert!(results[..256].iter().all(Result::is_ok));
+        assert!(results[256..].iter().all(Result::is_err));
+    }
+
+    #[test]
+    fn test_put_many_restore_failure() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        server.hang_up_after(2);
+        let results = conn.put_many(vec![PutJob::new(b"job").tube("jobs")]);
+        assert_eq!(1, *results[0].as_ref().unwrap());
+        assert!(matches!(
+            conn.using(),
+            Err(BeanstalkcError::ConnectionError(_))
+        ));
+        let mut conn = conn.reconnect().unwrap();
+        assert_eq!("default", conn.using().unwrap());
+    }
+}
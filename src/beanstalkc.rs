@@ -1,16 +1,30 @@
-use std::collections::HashMap;
-use std::net::TcpStream;
-use std::net::ToSocketAddrs;
+use std::io;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(unix)]
+use std::path::Path;
+use std::path::PathBuf;
+use std::thread;
 use std::time::Duration;
 
 use bufstream::BufStream;
 
+use crate::batch::{self, PutJob};
 use crate::command;
+use crate::command::Status;
 use crate::config::*;
 use crate::error::{BeanstalkcError, BeanstalkcResult};
-use crate::job::Job;
+use crate::job::{Job, Outcome, OwnedJob, ReserveOutcome};
+use crate::pipeline::Pipeline;
+use crate::reconnect::ReconnectPolicy;
 use crate::request::Request;
 use crate::response::Response;
+use crate::stats::{JobStats, ServerStats, TubeStats};
+#[cfg(feature = "tls")]
+use crate::tls::TlsConfig;
+use crate::transport::Transport;
+use crate::url::ConnectionUrl;
 
 /// `Beanstalkc` provides beanstalkd client operations.
 #[derive(Debug)]
@@ -18,7 +32,16 @@ pub struct Beanstalkc {
     host: String,
     port: u16,
     connection_timeout: Option<Duration>,
-    stream: Option<BufStream<TcpStream>>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    socket_path: Option<PathBuf>,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsConfig>,
+    reconnect_policy: Option<ReconnectPolicy>,
+    // The session state, replayed whenever the connection is (re-)established.
+    tube: Option<String>,
+    watch_list: Vec<String>,
+    stream: Option<BufStream<Box<dyn Transport>>>,
 }
 
 impl Beanstalkc {
@@ -29,10 +52,62 @@ impl Beanstalkc {
             host: DEFAULT_HOST.to_string(),
             port: DEFAULT_PORT,
             connection_timeout: DEFAULT_CONNECTION_TIMEOUT,
+            read_timeout: None,
+            write_timeout: None,
+            socket_path: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+            reconnect_policy: None,
+            tube: None,
+            watch_list: vec![],
             stream: None,
         }
     }
 
+    /// Create a new `Beanstalkc` instance connecting to a beanstalkd server listening
+    /// on a Unix domain socket (`beanstalkd -l unix:/path`).
+    ///
+    /// # Example:
+    ///
+    /// ```no_run
+    /// use beanstalkc::Beanstalkc;
+    ///
+    /// let mut conn = Beanstalkc::unix("/run/beanstalkd.sock").connect().unwrap();
+    /// ```
+    #[cfg(unix)]
+    pub fn unix<P: AsRef<Path>>(path: P) -> Beanstalkc {
+        let mut conn = Beanstalkc::new();
+        conn.socket_path = Some(path.as_ref().to_path_buf());
+        conn
+    }
+
+    /// Create a new `Beanstalkc` instance configured from a connection URL of the form
+    /// `beanstalk://host[:port][?timeout=5s&use=tube&watch=tube1,tube2]`.
+    ///
+    /// `timeout` is the connection timeout (`500ms`, `5s`, `1m` or `none`), `use` is the
+    /// tube used once connected, and `watch` replaces the default watch list.
+    ///
+    /// # Example:
+    ///
+    /// ```no_run
+    /// use beanstalkc::Beanstalkc;
+    ///
+    /// let mut conn = Beanstalkc::from_url("beanstalk://localhost:11300?use=jobs&watch=a,b")
+    ///     .unwrap()
+    ///     .connect()
+    ///     .unwrap();
+    /// ```
+    pub fn from_url(url: &str) -> BeanstalkcResult<Beanstalkc> {
+        let url = ConnectionUrl::parse(url)?;
+        let mut conn = Beanstalkc::new()
+            .host(&url.host)
+            .port(url.port)
+            .connection_timeout(url.connection_timeout);
+        conn.tube = url.tube;
+        conn.watch_list = url.watch_list;
+        Ok(conn)
+    }
+
     /// Change host to beanstalkd server.
     ///
     /// # Example:
@@ -80,6 +155,125 @@ impl Beanstalkc {
         self
     }
 
+    /// Set timeout for reading a response from the beanstalkd server.
+    /// Default is `None`, blocking forever.
+    ///
+    /// On timeout the connection is closed, because the response may still arrive
+    /// later, and `BeanstalkcError::SocketTimeout` is returned. `reserve` ignores this
+    /// timeout and `reserve_with_timeout` extends it by the reserve timeout.
+    ///
+    /// # Example:
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use beanstalkc::Beanstalkc;
+    ///
+    /// let mut conn = Beanstalkc::new()
+    ///        .read_timeout(Some(Duration::from_secs(5)))
+    ///        .connect()
+    ///        .unwrap();
+    /// ```
+    pub fn read_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Set timeout for writing a command to the beanstalkd server.
+    /// Default is `None`, blocking forever.
+    ///
+    /// # Example:
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use beanstalkc::Beanstalkc;
+    ///
+    /// let mut conn = Beanstalkc::new()
+    ///        .write_timeout(Some(Duration::from_secs(5)))
+    ///        .connect()
+    ///        .unwrap();
+    /// ```
+    pub fn write_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.write_timeout = timeout;
+        self
+    }
+
+    /// Connect over TLS using the given configuration. Requires the `tls` feature.
+    ///
+    /// The handshake must complete within the connection timeout. TLS is not supported
+    /// over Unix domain sockets, connecting fails with `ConnectionError` instead.
+    ///
+    /// # Example:
+    ///
+    /// ```no_run
+    /// use beanstalkc::{Beanstalkc, TlsConfig};
+    ///
+    /// let mut conn = Beanstalkc::new()
+    ///        .host("queue.internal")
+    ///        .tls(TlsConfig::new().webpki_roots())
+    ///        .connect()
+    ///        .unwrap();
+    /// ```
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, config: TlsConfig) -> Self {
+        self.tls = Some(config);
+        self
+    }
+
+    /// Reconnect automatically when the connection turns out to be broken.
+    ///
+    /// The used tube and the watch list are restored on the new connection, then the
+    /// failed command is sent again if it is idempotent (listing, peeking, stats,
+    /// `use`, `watch`, `ignore` and `pause-tube`). Other commands return the error,
+    /// since the server may have executed them already, and jobs reserved on the
+    /// broken connection are released by the server.
+    ///
+    /// # Example:
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use beanstalkc::{Beanstalkc, ReconnectPolicy};
+    ///
+    /// let mut conn = Beanstalkc::new()
+    ///        .auto_reconnect(ReconnectPolicy::new().initial_delay(Duration::from_secs(1)))
+    ///        .connect()
+    ///        .unwrap();
+    /// ```
+    pub fn auto_reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect_policy = Some(policy);
+        self
+    }
+
+    /// Set the tube to use once connected.
+    ///
+    /// # Example:
+    ///
+    /// ```no_run
+    /// use beanstalkc::Beanstalkc;
+    ///
+    /// let mut conn = Beanstalkc::new().initial_tube("jobs").connect().unwrap();
+    /// ```
+    pub fn initial_tube(mut self, name: &str) -> Self {
+        self.tube = Some(name.to_string());
+        self
+    }
+
+    /// Set the tubes to watch once connected, replacing the default watch list.
+    ///
+    /// # Example:
+    ///
+    /// ```no_run
+    /// use beanstalkc::Beanstalkc;
+    ///
+    /// let mut conn = Beanstalkc::new()
+    ///        .initial_watch_list(&["emails", "reports"])
+    ///        .connect()
+    ///        .unwrap();
+    /// ```
+    pub fn initial_watch_list(mut self, names: &[&str]) -> Self {
+        self.watch_list = names.iter().map(|x| x.to_string()).collect();
+        self
+    }
+
     /// Connect to a running beanstalkd server.
     ///
     /// # Examples
@@ -106,30 +300,92 @@ impl Beanstalkc {
     ///        .unwrap();
     /// ```
     pub fn connect(mut self) -> BeanstalkcResult<Self> {
-        let addr = format!("{}:{}", self.host, self.port);
-        let tcp_stream = match self.connection_timeout {
-            Some(timeout) => {
-                let addresses: Vec<_> = addr
-                    .to_socket_addrs()
-                    .unwrap_or_else(|_| panic!("failed to parse address: {}", addr))
-                    .filter(|x| x.is_ipv4())
-                    .collect();
-                // FIXME: maybe we should try every possible addresses?
-                TcpStream::connect_timeout(&addresses.first().unwrap(), timeout)?
+        self.open()?;
+        Ok(self)
+    }
+
+    fn open(&mut self) -> BeanstalkcResult<()> {
+        let transport = match &self.socket_path {
+            #[cfg(all(unix, feature = "tls"))]
+            Some(path) if self.tls.is_some() => {
+                return Err(BeanstalkcError::ConnectionError(format!(
+                    "TLS is not supported over Unix domain sockets ({})",
+                    path.display()
+                )));
             }
-            None => TcpStream::connect(&addr)?,
+            #[cfg(unix)]
+            Some(path) => connect_unix(path)?,
+            _ => self.connect_tcp()?,
         };
-        self.stream = Some(BufStream::new(tcp_stream));
-        Ok(self)
+        transport.set_read_timeout(self.read_timeout)?;
+        transport.set_write_timeout(self.write_timeout)?;
+        self.stream = Some(BufStream::new(transport));
+        self.apply_initial_tubes()
+    }
+
+    fn connect_tcp(&self) -> BeanstalkcResult<Box<dyn Transport>> {
+        let addr = format!("{}:{}", self.host, self.port);
+        let addresses: Vec<SocketAddr> = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .map_err(|e| {
+                BeanstalkcError::ConnectionError(format!("failed to resolve {}: {}", addr, e))
+            })?
+            .collect();
+        if addresses.is_empty() {
+            return Err(BeanstalkcError::ConnectionError(format!(
+                "no addresses found for {}",
+                addr
+            )));
+        }
+
+        let mut failures = vec![];
+        for address in addresses {
+            let result = match self.connection_timeout {
+                Some(timeout) => TcpStream::connect_timeout(&address, timeout),
+                None => TcpStream::connect(address),
+            };
+            match result {
+                #[cfg(feature = "tls")]
+                Ok(tcp_stream) if self.tls.is_some() => {
+                    let tls = self.tls.as_ref().unwrap();
+                    return tls.connect(&self.host, tcp_stream, self.connection_timeout);
+                }
+                Ok(tcp_stream) => return Ok(Box::new(tcp_stream)),
+                Err(e) => failures.push(format!("{}: {}", address, e)),
+            }
+        }
+
+        Err(BeanstalkcError::ConnectionError(format!(
+            "failed to connect to {} ({})",
+            addr,
+            failures.join(", ")
+        )))
+    }
+
+    fn apply_initial_tubes(&mut self) -> BeanstalkcResult<()> {
+        if let Some(tube) = self.tube.clone() {
+            self.use_tube(&tube)?;
+        }
+
+        let watch_list = self.watch_list.clone();
+        for tube in &watch_list {
+            self.watch(tube)?;
+        }
+        if !watch_list.is_empty() && !watch_list.iter().any(|x| x == DEFAULT_TUBE) {
+            self.ignore(DEFAULT_TUBE)?;
+        }
+        Ok(())
     }
 
     /// Close connection to remote server.
-    #[allow(unused_must_use)]
-    fn close(&mut self) {
-        self.send(command::quit());
+    pub(crate) fn close(&mut self) {
+        if let Some(stream) = self.stream.as_mut() {
+            let _ = Request::new(stream).send(&command::quit().build());
+        }
+        self.stream = None;
     }
 
-    /// Re-connect to the beanstalkd server.
+    /// Re-connect to the beanstalkd server, restoring the used tube and the watch list.
     ///
     /// # Example
     ///
@@ -166,11 +422,15 @@ impl Beanstalkc {
 
     /// Put a job into the current tube and return the job id.
     ///
+    /// If the server runs out of memory for its priority queue, the job is still
+    /// created but buried. This is reported as `BeanstalkcError::Buried` carrying
+    /// the new job id, so the job can be kicked instead of being put again.
+    ///
     /// # Example
     ///
     /// ```no_run
     /// use std::time::Duration;
-    /// use beanstalkc::Beanstalkc;
+    /// use beanstalkc::{Beanstalkc, BeanstalkcError};
     ///
     /// let mut conn = Beanstalkc::new().connect().unwrap();
     ///
@@ -180,6 +440,12 @@ impl Beanstalkc {
     ///        Duration::from_secs(1),
     ///        Duration::from_secs(10),
     ///    );
+    ///
+    /// match conn.put_default(b"Rust") {
+    ///     Ok(job_id) => println!("inserted {}", job_id),
+    ///     Err(BeanstalkcError::Buried(job_id)) => conn.kick_job(job_id).unwrap(),
+    ///     Err(e) => panic!("{}", e),
+    /// }
     /// ```
     pub fn put(
         &mut self,
@@ -192,34 +458,79 @@ impl Beanstalkc {
             .and_then(|r| r.job_id())
     }
 
-    /// Reserve a job from one of those watched tubes. Return a `Job` object if it succeeds.
+    /// Put many jobs through a pipeline instead of waiting for a reply after each
+    /// job. Return one result per job, in order.
+    ///
+    /// Jobs given a tube are put into it through `use`, and the current tube is used
+    /// again afterwards. If that fails, the connection is closed, so that the next
+    /// command reports the failure or reconnects into the current tube. After a
+    /// connection failure, the jobs reporting it may have been created anyway.
     ///
     /// # Example
     ///
     /// ```no_run
-    /// use beanstalkc::Beanstalkc;
+    /// use std::time::Duration;
+    /// use beanstalkc::{Beanstalkc, PutJob};
     ///
     /// let mut conn = Beanstalkc::new().connect().unwrap();
     ///
-    /// let mut job = conn.reserve().unwrap();
-    /// // Execute job...
-    /// dbg!(job.id());
-    /// dbg!(job.body());
+    /// let results = conn
+    ///     .put_many(vec![
+    ///         PutJob::new(b"first"),
+    ///         PutJob::new(b"second").priority(10).tube("reports"),
+    ///         (&b"third"[..], 0, Duration::from_secs(5), Duration::from_secs(60)).into(),
+    ///     ]);
+    /// for result in results {
+    ///     match result {
+    ///         Ok(job_id) => println!("inserted {}", job_id),
+    ///         Err(e) => println!("failed: {}", e),
+    ///     }
+    /// }
+    /// ```
+    pub fn put_many<'a, I>(&mut self, jobs: I) -> Vec<BeanstalkcResult<u64>>
+    where
+        I: IntoIterator,
+        I::Item: Into<PutJob<'a>>,
+    {
+        batch::put_many(self, jobs)
+    }
+
+    /// Reserve a job from one of those watched tubes.
     ///
-    /// job.delete().unwrap();
+    /// Return `ReserveOutcome::Job` if it succeeds, or `ReserveOutcome::DeadlineSoon`
+    /// if a job reserved by this client is about to exceed its TTR.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use beanstalkc::{Beanstalkc, ReserveOutcome};
+    ///
+    /// let mut conn = Beanstalkc::new().connect().unwrap();
+    ///
+    /// match conn.reserve().unwrap() {
+    ///     ReserveOutcome::Job(mut job) => {
+    ///         // Execute job...
+    ///         dbg!(job.id());
+    ///         dbg!(job.body());
+    ///
+    ///         job.delete().unwrap();
+    ///     }
+    ///     ReserveOutcome::DeadlineSoon => {
+    ///         // Touch, release or delete in-flight jobs...
+    ///     }
+    ///     ReserveOutcome::TimedOut => unreachable!(),
+    /// }
     /// ```
-    pub fn reserve(&mut self) -> BeanstalkcResult<Job> {
-        let resp = self.send(command::reserve(None))?;
-        Ok(Job::new(
-            self,
-            resp.job_id()?,
-            Vec::from(resp.body.unwrap_or_default()),
-            true,
-        ))
+    pub fn reserve(&mut self) -> BeanstalkcResult<ReserveOutcome<'_>> {
+        let resp = self.send_with_read_timeout(command::reserve(None), None)?;
+        self.reserve_outcome(resp)
     }
 
     /// Reserve a job with given timeout from one of those watched tubes.
-    /// Return a `Job` object if it succeeds.
+    ///
+    /// Return `ReserveOutcome::Job` if it succeeds, `ReserveOutcome::TimedOut` if no job
+    /// became available in time, or `ReserveOutcome::DeadlineSoon` if a job reserved by
+    /// this client is about to exceed its TTR.
     ///
     /// # Example
     ///
@@ -229,19 +540,58 @@ impl Beanstalkc {
     ///
     /// let mut conn = Beanstalkc::new().connect().unwrap();
     ///
-    /// let mut job = conn.reserve_with_timeout(Duration::from_secs(10)).unwrap();
-    /// // Execute job...
-    /// dbg!(job.id());
-    /// dbg!(job.body());
+    /// let outcome = conn.reserve_with_timeout(Duration::from_secs(10)).unwrap();
+    /// if let Some(mut job) = outcome.job() {
+    ///     // Execute job...
+    ///     dbg!(job.id());
+    ///     dbg!(job.body());
+    ///
+    ///     job.delete().unwrap();
+    /// }
+    /// ```
+    pub fn reserve_with_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> BeanstalkcResult<ReserveOutcome<'_>> {
+        let read_timeout = self.read_timeout.map(|t| t + timeout);
+        let resp = self.send_with_read_timeout(command::reserve(Some(timeout)), read_timeout)?;
+        self.reserve_outcome(resp)
+    }
+
+    fn reserve_outcome(&mut self, resp: Response) -> BeanstalkcResult<ReserveOutcome<'_>> {
+        match resp.status {
+            Status::DeadlineSoon => Ok(ReserveOutcome::DeadlineSoon),
+            Status::TimedOut => Ok(ReserveOutcome::TimedOut),
+            _ => Ok(ReserveOutcome::Job(Job::new(
+                self,
+                resp.job_id()?,
+                resp.body.unwrap_or_default(),
+                true,
+            ))),
+        }
+    }
+
+    /// Reserve a specific job by its id, whatever state it is in.
+    /// Return a `Job` object if it succeeds.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use beanstalkc::Beanstalkc;
+    ///
+    /// let mut conn = Beanstalkc::new().connect().unwrap();
+    ///
+    /// let mut job = conn.reserve_job(1).unwrap();
+    /// assert_eq!(1, job.id());
     ///
     /// job.delete().unwrap();
     /// ```
-    pub fn reserve_with_timeout(&mut self, timeout: Duration) -> BeanstalkcResult<Job> {
-        let resp = self.send(command::reserve(Some(timeout)))?;
+    pub fn reserve_job(&mut self, job_id: u64) -> BeanstalkcResult<Job<'_>> {
+        let resp = self.send(command::reserve_job(job_id))?;
         Ok(Job::new(
             self,
             resp.job_id()?,
-            Vec::from(resp.body.unwrap_or_default()),
+            resp.body.unwrap_or_default(),
             true,
         ))
     }
@@ -289,7 +639,7 @@ impl Beanstalkc {
     /// let mut job = conn.peek(1).unwrap();
     /// assert_eq!(1, job.id());
     /// ```
-    pub fn peek(&mut self, job_id: u64) -> BeanstalkcResult<Job> {
+    pub fn peek(&mut self, job_id: u64) -> BeanstalkcResult<Job<'_>> {
         self.do_peek(command::peek_job(job_id))
     }
 
@@ -306,7 +656,7 @@ impl Beanstalkc {
     /// dbg!(job.id());
     /// dbg!(job.body());
     /// ```
-    pub fn peek_ready(&mut self) -> BeanstalkcResult<Job> {
+    pub fn peek_ready(&mut self) -> BeanstalkcResult<Job<'_>> {
         self.do_peek(command::peek_ready())
     }
 
@@ -323,7 +673,7 @@ impl Beanstalkc {
     /// dbg!(job.id());
     /// dbg!(job.body());
     /// ```
-    pub fn peek_delayed(&mut self) -> BeanstalkcResult<Job> {
+    pub fn peek_delayed(&mut self) -> BeanstalkcResult<Job<'_>> {
         self.do_peek(command::peek_delayed())
     }
 
@@ -340,16 +690,16 @@ impl Beanstalkc {
     /// dbg!(job.id());
     /// dbg!(job.body());
     /// ```
-    pub fn peek_buried(&mut self) -> BeanstalkcResult<Job> {
+    pub fn peek_buried(&mut self) -> BeanstalkcResult<Job<'_>> {
         self.do_peek(command::peek_buried())
     }
 
-    pub fn do_peek(&mut self, cmd: command::Command) -> BeanstalkcResult<Job> {
+    pub fn do_peek(&mut self, cmd: command::Command) -> BeanstalkcResult<Job<'_>> {
         let resp = self.send(cmd)?;
         Ok(Job::new(
             self,
             resp.job_id()?,
-            Vec::from(resp.body.unwrap_or_default()),
+            resp.body.unwrap_or_default(),
             false,
         ))
     }
@@ -367,7 +717,7 @@ impl Beanstalkc {
     /// assert!(tubes.contains(&String::from("default")));
     /// ```
     pub fn tubes(&mut self) -> BeanstalkcResult<Vec<String>> {
-        Ok(self.send(command::tubes())?.body_as_vec()?)
+        self.send(command::tubes())?.body_as_vec()
     }
 
     /// Return the tube currently being used.
@@ -399,8 +749,11 @@ impl Beanstalkc {
     /// assert_eq!("jobs".to_string(), tube);
     /// ```
     pub fn use_tube(&mut self, name: &str) -> BeanstalkcResult<String> {
-        self.send(command::use_tube(name))
-            .and_then(|r| r.get_param(0))
+        let tube = self
+            .send(command::use_tube(name))
+            .and_then(|r| r.get_param(0))?;
+        self.record_use(&tube);
+        Ok(tube)
     }
 
     /// Return a list of tubes currently being watched.
@@ -416,7 +769,7 @@ impl Beanstalkc {
     /// assert_eq!(vec!["default".to_string()], tubes);
     /// ```
     pub fn watching(&mut self) -> BeanstalkcResult<Vec<String>> {
-        Ok(self.send(command::watching())?.body_as_vec()?)
+        self.send(command::watching())?.body_as_vec()
     }
 
     /// Watch a specific tube.
@@ -432,8 +785,11 @@ impl Beanstalkc {
     /// assert_eq!(2, watched_count);
     /// ```
     pub fn watch(&mut self, name: &str) -> BeanstalkcResult<u64> {
-        self.send(command::watch(name))
-            .and_then(|r| r.get_int_param(0))
+        let count = self
+            .send(command::watch(name))
+            .and_then(|r| r.get_int_param(0))?;
+        self.record_watch(name);
+        Ok(count)
     }
 
     /// Stop watching a specific tube.
@@ -447,11 +803,14 @@ impl Beanstalkc {
     /// conn.ignore("foo").unwrap();
     /// ```
     pub fn ignore(&mut self, name: &str) -> BeanstalkcResult<u64> {
-        self.send(command::ignore(name))
-            .and_then(|r| r.get_int_param(0))
+        let count = self
+            .send(command::ignore(name))
+            .and_then(|r| r.get_int_param(0))?;
+        self.record_ignore(name);
+        Ok(count)
     }
 
-    /// Return a dict of statistical information about the beanstalkd server.
+    /// Return statistical information about the beanstalkd server.
     ///
     /// # Example
     ///
@@ -462,11 +821,11 @@ impl Beanstalkc {
     ///
     /// dbg!(conn.stats().unwrap());
     /// ```
-    pub fn stats(&mut self) -> BeanstalkcResult<HashMap<String, String>> {
-        Ok(self.send(command::stats())?.body_as_map()?)
+    pub fn stats(&mut self) -> BeanstalkcResult<ServerStats> {
+        self.send(command::stats())?.body_as()
     }
 
-    /// Return a dict of statistical information about the specified tube.
+    /// Return statistical information about the specified tube.
     ///
     /// # Example
     ///
@@ -477,8 +836,8 @@ impl Beanstalkc {
     ///
     /// dbg!(conn.stats_tube("default").unwrap());
     /// ```
-    pub fn stats_tube(&mut self, name: &str) -> BeanstalkcResult<HashMap<String, String>> {
-        Ok(self.send(command::stats_tube(name))?.body_as_map()?)
+    pub fn stats_tube(&mut self, name: &str) -> BeanstalkcResult<TubeStats> {
+        self.send(command::stats_tube(name))?.body_as()
     }
 
     /// Pause the specific tube for `delay` time.
@@ -507,7 +866,7 @@ impl Beanstalkc {
     ///
     /// conn.delete(123).unwrap();
     ///
-    /// let mut job = conn.reserve().unwrap();
+    /// let mut job = conn.reserve().unwrap().into_job().unwrap();
     /// // Recommended way to delete a job
     /// job.delete().unwrap();
     /// ```
@@ -593,7 +952,7 @@ impl Beanstalkc {
         self.send(command::touch(job_id)).map(|_| ())
     }
 
-    /// Return a dict of statistical information about a job.
+    /// Return statistical information about a job.
     ///
     /// # Example
     ///
@@ -605,30 +964,257 @@ impl Beanstalkc {
     /// let stats = conn.stats_job(1).unwrap();
     /// dbg!(stats);
     /// ```
-    pub fn stats_job(&mut self, job_id: u64) -> BeanstalkcResult<HashMap<String, String>> {
-        Ok(self.send(command::stats_job(job_id))?.body_as_map()?)
+    pub fn stats_job(&mut self, job_id: u64) -> BeanstalkcResult<JobStats> {
+        self.send(command::stats_job(job_id))?.body_as()
     }
 
-    fn send(&mut self, cmd: command::Command) -> BeanstalkcResult<Response> {
-        if self.stream.is_none() {
+    /// Finish a detached job by deleting, releasing or burying it.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use beanstalkc::{Beanstalkc, Outcome};
+    ///
+    /// let mut conn = Beanstalkc::new().connect().unwrap();
+    ///
+    /// let job = conn.reserve().unwrap().into_job().unwrap().detach();
+    /// conn.finish(
+    ///     job,
+    ///     Outcome::Release {
+    ///         priority: 0,
+    ///         delay: Duration::from_secs(10),
+    ///     },
+    /// )
+    /// .unwrap();
+    /// ```
+    pub fn finish(&mut self, job: OwnedJob, outcome: Outcome) -> BeanstalkcResult<()> {
+        match outcome {
+            Outcome::Delete => self.delete(job.id()),
+            Outcome::Release { priority, delay } => self.release(job.id(), priority, delay),
+            Outcome::Bury { priority } => self.bury(job.id(), priority),
+        }
+    }
+
+    /// Attach a detached job to this connection again, to use the `Job` methods on it.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use beanstalkc::Beanstalkc;
+    ///
+    /// let mut conn = Beanstalkc::new().connect().unwrap();
+    ///
+    /// let job = conn.reserve().unwrap().into_job().unwrap().detach();
+    /// conn.attach(job).touch().unwrap();
+    /// ```
+    pub fn attach(&mut self, job: OwnedJob) -> Job<'_> {
+        let reserved = job.reserved();
+        let id = job.id();
+        Job::new(self, id, job.into_body(), reserved)
+    }
+
+    /// Create a pipeline of commands, written in one go and answered in order.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use beanstalkc::Beanstalkc;
+    ///
+    /// let mut conn = Beanstalkc::new().connect().unwrap();
+    ///
+    /// let replies = conn
+    ///     .pipeline()
+    ///     .put_default(b"first")
+    ///     .put_default(b"second")
+    ///     .delete(1)
+    ///     .execute();
+    /// for reply in replies {
+    ///     dbg!(reply);
+    /// }
+    /// ```
+    pub fn pipeline(&mut self) -> Pipeline<'_> {
+        Pipeline::new(self)
+    }
+
+    /// Return the server address, which identifies the server regardless of the
+    /// connection state.
+    pub(crate) fn address(&self) -> String {
+        match &self.socket_path {
+            Some(path) => path.display().to_string(),
+            None => format!("{}:{}", self.host, self.port),
+        }
+    }
+
+    pub(crate) fn current_tube(&self) -> &str {
+        self.tube.as_deref().unwrap_or(DEFAULT_TUBE)
+    }
+
+    pub(crate) fn watched_tubes(&self) -> Vec<String> {
+        if self.watch_list.is_empty() {
+            vec![DEFAULT_TUBE.to_string()]
+        } else {
+            self.watch_list.clone()
+        }
+    }
+
+    // Keep the session state in sync with successful use, watch and ignore commands,
+    // so that it can be replayed on reconnect.
+    pub(crate) fn record_use(&mut self, name: &str) {
+        self.tube = Some(name.to_string());
+    }
+
+    pub(crate) fn record_watch(&mut self, name: &str) {
+        if self.watch_list.is_empty() {
+            self.watch_list.push(DEFAULT_TUBE.to_string());
+        }
+        if !self.watch_list.iter().any(|x| x == name) {
+            self.watch_list.push(name.to_string());
+        }
+    }
+
+    pub(crate) fn record_ignore(&mut self, name: &str) {
+        if self.watch_list.is_empty() {
+            self.watch_list.push(DEFAULT_TUBE.to_string());
+        }
+        self.watch_list.retain(|x| x != name);
+    }
+
+    /// Send the commands with a single flush and return their raw responses.
+    ///
+    /// Commands are never sent again on reconnect, since some of them may have been
+    /// executed already. On error the connection is closed, as it is out of sync.
+    pub(crate) fn send_many(
+        &mut self,
+        cmds: &[command::Command],
+    ) -> BeanstalkcResult<Vec<Response>> {
+        self.ensure_connected()?;
+
+        let messages: Vec<_> = cmds.iter().map(|cmd| cmd.build()).collect();
+        let result = Request::new(self.stream.as_mut().unwrap()).send_many(&messages);
+        if result.is_err() {
+            self.stream = None;
+        }
+        result
+    }
+
+    fn ensure_connected(&mut self) -> BeanstalkcResult<()> {
+        if self.stream.is_some() {
+            return Ok(());
+        }
+        if self.reconnect_policy.is_none() {
             return Err(BeanstalkcError::ConnectionError(
                 "invalid connection".to_string(),
             ));
         }
+        self.reconnect_with_backoff()
+    }
+
+    fn send(&mut self, cmd: command::Command) -> BeanstalkcResult<Response> {
+        self.ensure_connected()?;
+
+        match self.send_once(&cmd) {
+            Err(e) if self.reconnect_policy.is_some() && is_disconnected(&e) => {
+                self.stream = None;
+                self.reconnect_with_backoff()?;
+                if cmd.idempotent {
+                    self.send_once(&cmd)
+                } else {
+                    Err(e)
+                }
+            }
+            result => result,
+        }
+    }
 
+    fn send_once(&mut self, cmd: &command::Command) -> BeanstalkcResult<Response> {
         let mut request = Request::new(self.stream.as_mut().unwrap());
-        let resp = request.send(cmd.build().as_bytes())?;
+        let resp = match request.send(&cmd.build()) {
+            Ok(resp) => resp,
+            Err(e) => {
+                if let BeanstalkcError::SocketTimeout = e {
+                    // The response may still arrive, the connection is out of sync.
+                    self.stream = None;
+                }
+                return Err(e);
+            }
+        };
 
-        if cmd.expected_ok_status.contains(&resp.status) {
-            Ok(resp)
-        } else if cmd.expected_error_status.contains(&resp.status) {
-            Err(BeanstalkcError::CommandFailed(format!("{:?}", resp.status)))
-        } else {
-            Err(BeanstalkcError::UnexpectedResponse(format!(
-                "{:?}",
-                resp.status
-            )))
+        cmd.check(resp)
+    }
+
+    /// Re-establish the connection following the reconnect policy.
+    fn reconnect_with_backoff(&mut self) -> BeanstalkcResult<()> {
+        // Taken out so that a failure while replaying the session state doesn't
+        // recurse into another reconnect.
+        let policy = self.reconnect_policy.take().unwrap();
+        let mut result = Err(BeanstalkcError::ConnectionError(
+            "no reconnect attempt allowed".to_string(),
+        ));
+        for attempt in 0..policy.attempts() {
+            thread::sleep(policy.delay(attempt));
+            self.stream = None;
+            result = self.open();
+            if result.is_ok() {
+                break;
+            }
         }
+        if result.is_err() {
+            self.stream = None;
+        }
+        self.reconnect_policy = Some(policy);
+        result
+    }
+
+    /// Send a command with the read timeout temporarily replaced by `timeout`.
+    fn send_with_read_timeout(
+        &mut self,
+        cmd: command::Command,
+        timeout: Option<Duration>,
+    ) -> BeanstalkcResult<Response> {
+        if self.read_timeout.is_none() || self.stream.is_none() {
+            return self.send(cmd);
+        }
+
+        self.set_stream_read_timeout(timeout)?;
+        let resp = self.send(cmd);
+        if self.stream.is_some() {
+            self.set_stream_read_timeout(self.read_timeout)?;
+        }
+        resp
+    }
+
+    fn set_stream_read_timeout(&self, timeout: Option<Duration>) -> BeanstalkcResult<()> {
+        let stream = self.stream.as_ref().unwrap();
+        stream.get_ref().set_read_timeout(timeout)?;
+        Ok(())
+    }
+}
+
+/// Whether the error means the connection is gone.
+fn is_disconnected(err: &BeanstalkcError) -> bool {
+    match err.io_kind() {
+        Some(kind) => matches!(
+            kind,
+            io::ErrorKind::BrokenPipe
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::NotConnected
+                | io::ErrorKind::UnexpectedEof
+        ),
+        None => false,
+    }
+}
+
+#[cfg(unix)]
+fn connect_unix(path: &Path) -> BeanstalkcResult<Box<dyn Transport>> {
+    match UnixStream::connect(path) {
+        Ok(stream) => Ok(Box::new(stream)),
+        Err(e) => Err(BeanstalkcError::ConnectionError(format!(
+            "failed to connect to {}: {}",
+            path.display(),
+            e
+        ))),
     }
 }
 
@@ -643,3 +1229,562 @@ impl Default for Beanstalkc {
         Beanstalkc::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::command::Status;
+    use crate::error::BeanstalkcError;
+    use crate::job::{Outcome, OwnedJob, ReserveOutcome};
+    use crate::mock::MockServer;
+    use crate::reconnect::ReconnectPolicy;
+    use crate::stats::JobState;
+    use crate::Beanstalkc;
+
+    fn round_trip(body: &[u8]) {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        let id = conn.put_default(body).unwrap();
+        let mut job = conn.reserve().unwrap().into_job().unwrap();
+        assert_eq!(id, job.id());
+        assert_eq!(body, job.body());
+        job.delete().unwrap();
+    }
+
+    #[test]
+    fn test_round_trip_empty_body() {
+        round_trip(b"");
+    }
+
+    #[test]
+    fn test_round_trip_invalid_utf8() {
+        round_trip(&[0xff, 0xfe, 0xfd, 0x80, 0xc3, 0x28]);
+    }
+
+    #[test]
+    fn test_round_trip_embedded_line_breaks() {
+        round_trip(b"\r\n");
+        round_trip(b"first\r\nsecond\r\n");
+        round_trip(b"\r\nreserve\r\nput 0 0 1 1\r\nx\r\n");
+    }
+
+    #[test]
+    fn test_round_trip_all_bytes() {
+        let body: Vec<u8> = (0..=255u8).cycle().take(64 * 1024).collect();
+        round_trip(&body);
+    }
+
+    #[test]
+    fn test_peek_binary_body() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        let body = [0x1f, 0x8b, 0x08, 0x00, b'\r', b'\n', 0xff];
+        let id = conn.put_default(&body).unwrap();
+        let job = conn.peek(id).unwrap();
+        assert_eq!(&body[..], job.body());
+    }
+
+    #[test]
+    fn test_reserve_job() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        conn.put_default(b"first").unwrap();
+        let id = conn.put_default(b"second").unwrap();
+
+        let mut job = conn.reserve_job(id).unwrap();
+        assert_eq!(id, job.id());
+        assert_eq!(b"second", job.body());
+        assert!(job.reserved());
+        job.delete().unwrap();
+
+        assert!(matches!(
+            conn.reserve_job(id),
+            Err(BeanstalkcError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn test_reserve_job_taken_by_another_client() {
+        let server = MockServer::start();
+        let mut producer = server.connect();
+        let mut worker = server.connect();
+
+        let id = producer.put_default(b"job").unwrap();
+        let _job = worker.reserve().unwrap().into_job().unwrap();
+        assert!(producer.reserve_job(id).is_err());
+    }
+
+    #[test]
+    fn test_stats_job() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        let id = conn
+            .put(b"job", 10, Duration::from_secs(0), Duration::from_secs(30))
+            .unwrap();
+        let stats = conn.stats_job(id).unwrap();
+        assert_eq!(id, stats.id);
+        assert_eq!("default", stats.tube);
+        assert_eq!(JobState::Ready, stats.state);
+        assert_eq!(10, stats.pri);
+        assert_eq!(Duration::from_secs(30), stats.ttr);
+        assert_eq!(0, stats.reserves);
+
+        let job = conn.reserve().unwrap().into_job().unwrap();
+        assert_eq!(id, job.id());
+        let stats = conn.stats_job(id).unwrap();
+        assert_eq!(JobState::Reserved, stats.state);
+        assert_eq!(1, stats.reserves);
+
+        assert!(conn.stats_job(id + 1).is_err());
+    }
+
+    #[test]
+    fn test_stats_tube() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        conn.use_tube("jobs").unwrap();
+        conn.put(b"a", 0, Duration::from_secs(0), Duration::from_secs(1))
+            .unwrap();
+        conn.put(b"b", 2048, Duration::from_secs(0), Duration::from_secs(1))
+            .unwrap();
+        conn.put(b"c", 0, Duration::from_secs(60), Duration::from_secs(1))
+            .unwrap();
+
+        let stats = conn.stats_tube("jobs").unwrap();
+        assert_eq!("jobs", stats.name);
+        assert_eq!(1, stats.current_jobs_urgent);
+        assert_eq!(2, stats.current_jobs_ready);
+        assert_eq!(1, stats.current_jobs_delayed);
+        assert_eq!(0, stats.current_jobs_buried);
+
+        assert!(conn.stats_tube("missing").is_err());
+    }
+
+    #[test]
+    fn test_stats() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        conn.put_default(b"job").unwrap();
+        let stats = conn.stats().unwrap();
+        assert_eq!(1, stats.current_jobs_ready);
+        assert_eq!(65535, stats.max_job_size);
+        assert_eq!("mock", stats.version);
+    }
+
+    #[test]
+    fn test_error_not_found() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        assert!(matches!(conn.delete(100), Err(BeanstalkcError::NotFound)));
+        assert!(matches!(conn.touch(100), Err(BeanstalkcError::NotFound)));
+        assert!(matches!(
+            conn.stats_tube("missing"),
+            Err(BeanstalkcError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn test_reserve_timed_out() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        let outcome = conn.reserve_with_timeout(Duration::from_secs(0)).unwrap();
+        assert!(matches!(outcome, ReserveOutcome::TimedOut));
+        assert!(matches!(outcome.into_job(), Err(BeanstalkcError::TimedOut)));
+    }
+
+    #[test]
+    fn test_reserve_deadline_soon() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        conn.put(b"job", 0, Duration::from_secs(0), Duration::from_secs(1))
+            .unwrap();
+        let id = conn.reserve().unwrap().into_job().unwrap().id();
+
+        let outcome = conn.reserve().unwrap();
+        assert!(matches!(outcome, ReserveOutcome::DeadlineSoon));
+        assert!(matches!(
+            outcome.into_job(),
+            Err(BeanstalkcError::DeadlineSoon)
+        ));
+
+        // The connection is still usable, so the worker can act on its job.
+        conn.delete(id).unwrap();
+        let outcome = conn.reserve_with_timeout(Duration::from_secs(0)).unwrap();
+        assert!(matches!(outcome, ReserveOutcome::TimedOut));
+    }
+
+    #[test]
+    fn test_error_carries_status() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        match conn.ignore("default") {
+            Err(BeanstalkcError::CommandFailed(status)) => {
+                assert_eq!(Status::NotIgnored, status)
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_error_io_kind() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 64];
+            let _ = stream.read(&mut buf).unwrap();
+            // Announce a longer body than we send, then hang up.
+            stream.write_all(b"FOUND 1 10\r\nabc").unwrap();
+        });
+
+        let mut conn = Beanstalkc::new()
+            .host("127.0.0.1")
+            .port(port)
+            .connect()
+            .unwrap();
+        let err = conn.peek(1).unwrap_err();
+        assert_eq!(Some(io::ErrorKind::UnexpectedEof), err.io_kind());
+    }
+
+    #[test]
+    fn test_read_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            // Accept and never answer.
+            let (stream, _) = listener.accept().unwrap();
+            thread::sleep(Duration::from_secs(5));
+            drop(stream);
+        });
+
+        let mut conn = Beanstalkc::new()
+            .host("127.0.0.1")
+            .port(port)
+            .read_timeout(Some(Duration::from_millis(100)))
+            .connect()
+            .unwrap();
+        assert!(matches!(conn.using(), Err(BeanstalkcError::SocketTimeout)));
+        // A late response would be taken for the next command's, so the
+        // connection is closed.
+        assert!(matches!(
+            conn.using(),
+            Err(BeanstalkcError::ConnectionError(_))
+        ));
+    }
+
+    #[test]
+    fn test_read_timeout_extended_by_reserve_timeout() {
+        let server = MockServer::start();
+        let mut conn = Beanstalkc::new()
+            .host("127.0.0.1")
+            .port(server.port())
+            .read_timeout(Some(Duration::from_millis(200)))
+            .connect()
+            .unwrap();
+
+        assert!(matches!(
+            conn.reserve_with_timeout(Duration::from_secs(1)).unwrap(),
+            ReserveOutcome::TimedOut
+        ));
+        conn.put_default(b"job").unwrap();
+        assert!(conn.reserve().unwrap().job().is_some());
+    }
+
+    #[test]
+    fn test_connect_refused() {
+        let port = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port()
+        };
+
+        for timeout in &[None, Some(Duration::from_secs(1))] {
+            let err = Beanstalkc::new()
+                .host("127.0.0.1")
+                .port(port)
+                .connection_timeout(*timeout)
+                .connect()
+                .unwrap_err();
+            match err {
+                BeanstalkcError::ConnectionError(msg) => {
+                    assert!(msg.contains(&format!("127.0.0.1:{}:", port)), "{}", msg)
+                }
+                other => panic!("unexpected error: {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_connect_unresolvable_host() {
+        let err = Beanstalkc::new()
+            .host("no-such-host.invalid")
+            .connect()
+            .unwrap_err();
+        assert!(matches!(err, BeanstalkcError::ConnectionError(_)));
+    }
+
+    #[test]
+    fn test_connect_ipv6() {
+        let listener = match TcpListener::bind("[::1]:0") {
+            Ok(listener) => listener,
+            // IPv6 is not available in this environment.
+            Err(_) => return,
+        };
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let _ = listener.accept();
+        });
+
+        assert!(Beanstalkc::new().host("::1").port(port).connect().is_ok());
+    }
+
+    #[test]
+    fn test_put_buried() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        server.bury_puts(true);
+        let id = match conn.put_default(b"job") {
+            Err(BeanstalkcError::Buried(id)) => id,
+            other => panic!("unexpected result: {:?}", other),
+        };
+        assert_eq!(JobState::Buried, conn.stats_job(id).unwrap().state);
+
+        server.bury_puts(false);
+        conn.kick_job(id).unwrap();
+        let job = conn.reserve().unwrap().into_job().unwrap();
+        assert_eq!(id, job.id());
+        assert_eq!(b"job", job.body());
+    }
+
+    #[test]
+    fn test_owned_job_across_threads() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        let first = conn.put_default(b"first").unwrap();
+        let second = conn.put_default(b"second").unwrap();
+
+        let detached = conn.reserve().unwrap().into_job().unwrap().detach();
+        let with_stats = conn.reserve().unwrap().into_job().unwrap();
+        let jobs: Vec<OwnedJob> = vec![detached, with_stats.detach_with_stats().unwrap()];
+        assert!(jobs[0].stats().is_none());
+        assert_eq!(JobState::Reserved, jobs[1].stats().unwrap().state);
+
+        // The connection is free while the jobs are being processed.
+        assert_eq!(2, conn.stats().unwrap().current_jobs_reserved);
+
+        let jobs = thread::spawn(move || {
+            assert_eq!(b"first", jobs[0].body());
+            assert_eq!(b"second", jobs[1].body());
+            jobs
+        })
+        .join()
+        .unwrap();
+
+        let mut jobs = jobs.into_iter();
+        conn.finish(jobs.next().unwrap(), Outcome::Delete).unwrap();
+        conn.finish(jobs.next().unwrap(), Outcome::Bury { priority: 5 })
+            .unwrap();
+        assert!(matches!(
+            conn.stats_job(first),
+            Err(BeanstalkcError::NotFound)
+        ));
+        let stats = conn.stats_job(second).unwrap();
+        assert_eq!(JobState::Buried, stats.state);
+        assert_eq!(5, stats.pri);
+    }
+
+    #[test]
+    fn test_attach_owned_job() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        let id = conn.put_default(b"job").unwrap();
+        let job = conn.reserve().unwrap().into_job().unwrap().detach();
+        assert!(job.reserved());
+
+        let mut job = conn.attach(job);
+        assert_eq!(id, job.id());
+        job.release(0, Duration::from_secs(0)).unwrap();
+        assert!(!job.reserved());
+        assert_eq!(JobState::Ready, conn.stats_job(id).unwrap().state);
+    }
+
+    #[test]
+    fn test_from_url() {
+        let server = MockServer::start();
+        let url = format!(
+            "beanstalk://127.0.0.1:{}?timeout=1s&use=jobs&watch=a,b",
+            server.port()
+        );
+        let mut conn = Beanstalkc::from_url(&url).unwrap().connect().unwrap();
+
+        assert_eq!("jobs", conn.using().unwrap());
+        assert_eq!(
+            vec!["a".to_string(), "b".to_string()],
+            conn.watching().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_from_url_keeps_default_when_watched() {
+        let server = MockServer::start();
+        let url = format!("beanstalk://127.0.0.1:{}?watch=default,b", server.port());
+        let mut conn = Beanstalkc::from_url(&url).unwrap().connect().unwrap();
+
+        assert_eq!("default", conn.using().unwrap());
+        assert_eq!(
+            vec!["default".to_string(), "b".to_string()],
+            conn.watching().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_from_malformed_url() {
+        assert!(matches!(
+            Beanstalkc::from_url("redis://localhost"),
+            Err(BeanstalkcError::InvalidUrl(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_socket() {
+        let server = MockServer::start_unix();
+        let mut conn = server.connect();
+
+        conn.use_tube("jobs").unwrap();
+        conn.watch("jobs").unwrap();
+        let id = conn.put_default(b"over a unix socket").unwrap();
+
+        let mut job = conn.reserve().unwrap().into_job().unwrap();
+        assert_eq!(id, job.id());
+        assert_eq!(b"over a unix socket", job.body());
+        job.delete().unwrap();
+        assert_eq!("jobs", conn.using().unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_socket_missing() {
+        let err = Beanstalkc::unix("/nonexistent/beanstalkd.sock")
+            .connect()
+            .unwrap_err();
+        match err {
+            BeanstalkcError::ConnectionError(msg) => {
+                assert!(msg.contains("/nonexistent/beanstalkd.sock"), "{}", msg)
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    fn reconnecting_client(server: &MockServer) -> Beanstalkc {
+        Beanstalkc::new()
+            .host("127.0.0.1")
+            .port(server.port())
+            .auto_reconnect(ReconnectPolicy::new().initial_delay(Duration::from_millis(10)))
+            .connect()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_auto_reconnect_replays_session() {
+        let server = MockServer::start();
+        let mut conn = reconnecting_client(&server);
+        conn.use_tube("jobs").unwrap();
+        conn.watch("jobs").unwrap();
+        conn.ignore("default").unwrap();
+
+        server.drop_connections();
+        assert_eq!("jobs", conn.using().unwrap());
+        assert_eq!(vec!["jobs".to_string()], conn.watching().unwrap());
+    }
+
+    #[test]
+    fn test_auto_reconnect_does_not_retry_put() {
+        let server = MockServer::start();
+        let mut conn = reconnecting_client(&server);
+        conn.use_tube("jobs").unwrap();
+
+        server.drop_connections();
+        let err = conn.put_default(b"job").unwrap_err();
+        assert_eq!(Some(io::ErrorKind::UnexpectedEof), err.io_kind());
+        assert!(matches!(
+            conn.stats_tube("jobs"),
+            Err(BeanstalkcError::NotFound)
+        ));
+
+        let id = conn.put_default(b"job").unwrap();
+        assert_eq!("jobs", conn.stats_job(id).unwrap().tube);
+    }
+
+    #[test]
+    fn test_auto_reconnect_gives_up() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            // Answer the first command, then go away for good.
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 64];
+            let _ = stream.read(&mut buf).unwrap();
+            stream.write_all(b"USING default\r\n").unwrap();
+        });
+
+        let mut conn = Beanstalkc::new()
+            .host("127.0.0.1")
+            .port(port)
+            .auto_reconnect(
+                ReconnectPolicy::new()
+                    .max_attempts(2)
+                    .initial_delay(Duration::from_millis(10)),
+            )
+            .connect()
+            .unwrap();
+        assert_eq!("default", conn.using().unwrap());
+        thread::sleep(Duration::from_millis(50));
+
+        assert!(matches!(
+            conn.using(),
+            Err(BeanstalkcError::ConnectionError(_))
+        ));
+    }
+
+    #[test]
+    fn test_no_auto_reconnect_by_default() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+        conn.use_tube("jobs").unwrap();
+
+        server.drop_connections();
+        assert!(conn.using().is_err());
+    }
+
+    #[test]
+    fn test_reconnect_replays_session() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+        conn.use_tube("jobs").unwrap();
+        conn.watch("jobs").unwrap();
+
+        let mut conn = conn.reconnect().unwrap();
+        assert_eq!("jobs", conn.using().unwrap());
+        assert_eq!(
+            vec!["default".to_string(), "jobs".to_string()],
+            conn.watching().unwrap()
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use std::str;
+
+use tokio_util::bytes::{Buf, BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::error::BeanstalkcError;
+use crate::response::Response;
+
+/// `Codec` frames beanstalkd responses out of a non-blocking byte stream, and
+/// writes the commands built by `Command::build`.
+#[derive(Debug, Default)]
+pub struct Codec;
+
+impl Decoder for Codec {
+    type Item = Response;
+    type Error = BeanstalkcError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Response>, BeanstalkcError> {
+        let line_len = match src.windows(2).position(|x| x == b"\r\n") {
+            Some(i) => i,
+            None => return Ok(None),
+        };
+
+        let mut response = Response::parse_line(str::from_utf8(&src[..line_len])?)?;
+        let header_len = line_len + 2;
+        let body_len = match response.body_len()? {
+            Some(len) => len,
+            None => {
+                src.advance(header_len);
+                return Ok(Some(response));
+            }
+        };
+
+        let frame_len = header_len + body_len + 2; // +2 trailing line break
+        if src.len() < frame_len {
+            src.reserve(frame_len - src.len());
+            return Ok(None);
+        }
+        response.body = Some(src[header_len..header_len + body_len].to_vec());
+        src.advance(frame_len);
+        Ok(Some(response))
+    }
+}
+
+impl Encoder<Vec<u8>> for Codec {
+    type Error = BeanstalkcError;
+
+    fn encode(&mut self, message: Vec<u8>, dst: &mut BytesMut) -> Result<(), BeanstalkcError> {
+        dst.put_slice(&message);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::Status;
+
+    #[test]
+    fn test_decode_without_body() {
+        let mut buf = BytesMut::from(&b"INSERTED 3\r\nUSING jobs\r\n"[..]);
+
+        let resp = Codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(Status::Inserted, resp.status);
+        assert_eq!(3, resp.job_id().unwrap());
+        let resp = Codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(Status::Using, resp.status);
+        assert!(Codec.decode(&mut buf).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_decode_partial_body() {
+        let mut buf = BytesMut::from(&b"RESERVED 7 5\r\nhel"[..]);
+        assert!(Codec.decode(&mut buf).unwrap().is_none());
+
+        buf.extend_from_slice(b"lo\r\n");
+        let resp = Codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(Status::Reserved, resp.status);
+        assert_eq!(Some(b"hello".to_vec()), resp.body);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_decode_binary_body() {
+        let mut buf = BytesMut::from(&b"FOUND 1 4\r\n\r\n\0\xff\r\n"[..]);
+        let resp = Codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(Some(b"\r\n\0\xff".to_vec()), resp.body);
+    }
+
+    #[test]
+    fn test_decode_partial_line() {
+        let mut buf = BytesMut::from(&b"INSERT"[..]);
+        assert!(Codec.decode(&mut buf).unwrap().is_none());
+        assert_eq!(6, buf.len());
+    }
+
+    #[test]
+    fn test_decode_unknown_status() {
+        let mut buf = BytesMut::from(&b"WHATEVER\r\n"[..]);
+        assert!(Codec.decode(&mut buf).is_err());
+    }
+}
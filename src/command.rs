@@ -1,8 +1,9 @@
+use std::fmt;
 use std::str::FromStr;
-use std::string::ToString;
 use std::time::Duration;
 
-use crate::error::BeanstalkcError;
+use crate::error::{BeanstalkcError, BeanstalkcResult};
+use crate::response::Response;
 
 #[derive(Debug)]
 pub enum CommandKind {
@@ -13,6 +14,7 @@ pub enum CommandKind {
     PeekBuried,
     Reserve,
     ReserveTimeout,
+    ReserveJob,
     Delete,
     Release,
     Bury,
@@ -32,8 +34,8 @@ pub enum CommandKind {
     PauseTube,
 }
 
-impl ToString for CommandKind {
-    fn to_string(&self) -> String {
+impl fmt::Display for CommandKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let cmd = match *self {
             CommandKind::Put => "put",
             CommandKind::PeekJob => "peek",
@@ -42,6 +44,7 @@ impl ToString for CommandKind {
             CommandKind::PeekBuried => "peek-buried",
             CommandKind::Reserve => "reserve",
             CommandKind::ReserveTimeout => "reserve-with-timeout",
+            CommandKind::ReserveJob => "reserve-job",
             CommandKind::Delete => "delete",
             CommandKind::Release => "release",
             CommandKind::Bury => "bury",
@@ -60,11 +63,12 @@ impl ToString for CommandKind {
             CommandKind::Quit => "quit",
             CommandKind::PauseTube => "pause-tube",
         };
-        cmd.to_string()
+        write!(f, "{}", cmd)
     }
 }
 
-#[derive(Debug, PartialEq)]
+/// Status line keyword returned by the beanstalkd server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Status {
     Ok,
     Found,
@@ -120,13 +124,44 @@ impl FromStr for Status {
             "JOB_TOO_BIG" => Status::JobTooBig,
             "PAUSED" => Status::Paused,
             _ => {
-                return Err(BeanstalkcError::CommandFailed(s.to_string()));
+                return Err(BeanstalkcError::UnexpectedResponse(s.to_string()));
             }
         };
         Ok(s)
     }
 }
 
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let status = match *self {
+            Status::Ok => "OK",
+            Status::Found => "FOUND",
+            Status::NotFound => "NOT_FOUND",
+            Status::Reserved => "RESERVED",
+            Status::DeadlineSoon => "DEADLINE_SOON",
+            Status::TimedOut => "TIMED_OUT",
+            Status::Deleted => "DELETED",
+            Status::Released => "RELEASED",
+            Status::Buried => "BURIED",
+            Status::Kicked => "KICKED",
+            Status::Using => "USING",
+            Status::Watching => "WATCHING",
+            Status::Touched => "TOUCHED",
+            Status::Inserted => "INSERTED",
+            Status::NotIgnored => "NOT_IGNORED",
+            Status::OutOfMemory => "OUT_OF_MEMORY",
+            Status::InternalError => "INTERNAL_ERROR",
+            Status::Draining => "DRAINING",
+            Status::BadFormat => "BAD_FORMAT",
+            Status::UnknownCommand => "UNKNOWN_COMMAND",
+            Status::ExpectedCRLF => "EXPECTED_CRLF",
+            Status::JobTooBig => "JOB_TOO_BIG",
+            Status::Paused => "PAUSED",
+        };
+        write!(f, "{}", status)
+    }
+}
+
 #[derive(Debug)]
 pub struct Command<'a> {
     kind: CommandKind,
@@ -134,6 +169,9 @@ pub struct Command<'a> {
     body: Option<&'a [u8]>,
     pub expected_ok_status: Vec<Status>,
     pub expected_error_status: Vec<Status>,
+    /// Whether the command can be sent again after the connection broke, without
+    /// knowing if the server executed it.
+    pub idempotent: bool,
 }
 
 impl<'a> Command<'a> {
@@ -150,33 +188,59 @@ impl<'a> Command<'a> {
             body,
             expected_ok_status: exp_ok_status,
             expected_error_status: exp_error_status,
+            idempotent: false,
         }
     }
 
-    pub fn build(&self) -> String {
-        const SPACE: &str = " ";
-        const LINE_BREAK: &str = "\r\n";
+    fn idempotent(mut self) -> Self {
+        self.idempotent = true;
+        self
+    }
 
-        let mut cmd = self.kind.to_string();
+    /// Turn the server response into an error unless its status is expected.
+    pub fn check(&self, resp: Response) -> BeanstalkcResult<Response> {
+        if self.expected_ok_status.contains(&resp.status) {
+            Ok(resp)
+        } else if self.expected_error_status.contains(&resp.status) {
+            Err(resp.to_error())
+        } else {
+            Err(BeanstalkcError::UnexpectedResponse(resp.status.to_string()))
+        }
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        const SPACE: &[u8] = b" ";
+        const LINE_BREAK: &[u8] = b"\r\n";
+
+        let mut cmd = self.kind.to_string().into_bytes();
 
         if !self.args.is_empty() {
-            cmd = cmd + SPACE + self.args.clone().join(SPACE).as_str();
+            cmd.extend_from_slice(SPACE);
+            cmd.extend_from_slice(self.args.join(" ").as_bytes());
         }
 
         if let Some(body) = self.body {
-            cmd = cmd + SPACE + body.len().to_string().as_str() + LINE_BREAK;
-
-            let utf8body = String::from_utf8_lossy(body);
-            cmd.push_str(&utf8body);
+            cmd.extend_from_slice(SPACE);
+            cmd.extend_from_slice(body.len().to_string().as_bytes());
+            cmd.extend_from_slice(LINE_BREAK);
+            cmd.extend_from_slice(body);
         }
-        cmd.push_str(LINE_BREAK);
+        cmd.extend_from_slice(LINE_BREAK);
 
         cmd
     }
 }
 
 // Construct commands
-pub fn put(body: &[u8], priority: u32, delay: Duration, ttr: Duration) -> Command {
+/// Round a duration up to whole seconds, the resolution of the protocol.
+pub fn whole_seconds(duration: Duration) -> Duration {
+    match duration.subsec_nanos() {
+        0 => duration,
+        _ => Duration::from_secs(duration.as_secs() + 1),
+    }
+}
+
+pub fn put(body: &[u8], priority: u32, delay: Duration, ttr: Duration) -> Command<'_> {
     Command::new(
         CommandKind::Put,
         vec![
@@ -198,10 +262,20 @@ pub fn reserve<'a>(timeout: Option<Duration>) -> Command<'a> {
         },
         timeout
             .map(|t| vec![t.as_secs().to_string()])
-            .unwrap_or_else(|| vec![]),
+            .unwrap_or_default(),
+        None,
+        vec![Status::Reserved, Status::TimedOut, Status::DeadlineSoon],
+        vec![],
+    )
+}
+
+pub fn reserve_job<'a>(job_id: u64) -> Command<'a> {
+    Command::new(
+        CommandKind::ReserveJob,
+        vec![job_id.to_string()],
         None,
         vec![Status::Reserved],
-        vec![Status::TimedOut, Status::DeadlineSoon],
+        vec![Status::NotFound],
     )
 }
 
@@ -249,6 +323,7 @@ fn peek<'a>(kind: CommandKind, args: Vec<String>) -> Command<'a> {
         vec![Status::Found],
         vec![Status::NotFound],
     )
+    .idempotent()
 }
 
 pub fn tubes<'a>() -> Command<'a> {
@@ -259,6 +334,7 @@ pub fn tubes<'a>() -> Command<'a> {
         vec![Status::Ok],
         vec![],
     )
+    .idempotent()
 }
 
 pub fn using<'a>() -> Command<'a> {
@@ -269,6 +345,7 @@ pub fn using<'a>() -> Command<'a> {
         vec![Status::Using],
         vec![],
     )
+    .idempotent()
 }
 
 pub fn use_tube<'a>(name: &str) -> Command<'a> {
@@ -279,6 +356,7 @@ pub fn use_tube<'a>(name: &str) -> Command<'a> {
         vec![Status::Using],
         vec![],
     )
+    .idempotent()
 }
 
 pub fn watching<'a>() -> Command<'a> {
@@ -289,6 +367,7 @@ pub fn watching<'a>() -> Command<'a> {
         vec![Status::Ok],
         vec![],
     )
+    .idempotent()
 }
 
 pub fn watch<'a>(name: &str) -> Command<'a> {
@@ -299,6 +378,7 @@ pub fn watch<'a>(name: &str) -> Command<'a> {
         vec![Status::Watching],
         vec![],
     )
+    .idempotent()
 }
 
 pub fn ignore<'a>(name: &str) -> Command<'a> {
@@ -309,10 +389,11 @@ pub fn ignore<'a>(name: &str) -> Command<'a> {
         vec![Status::Watching],
         vec![Status::NotIgnored],
     )
+    .idempotent()
 }
 
 pub fn stats<'a>() -> Command<'a> {
-    Command::new(CommandKind::Stats, vec![], None, vec![Status::Ok], vec![])
+    Command::new(CommandKind::Stats, vec![], None, vec![Status::Ok], vec![]).idempotent()
 }
 
 pub fn stats_tube<'a>(name: &str) -> Command<'a> {
@@ -323,6 +404,7 @@ pub fn stats_tube<'a>(name: &str) -> Command<'a> {
         vec![Status::Ok],
         vec![Status::NotFound],
     )
+    .idempotent()
 }
 
 pub fn pause_tube<'a>(name: &str, delay: Duration) -> Command<'a> {
@@ -333,6 +415,7 @@ pub fn pause_tube<'a>(name: &str, delay: Duration) -> Command<'a> {
         vec![Status::Paused],
         vec![Status::NotFound],
     )
+    .idempotent()
 }
 
 pub fn delete<'a>(job_id: u64) -> Command<'a> {
@@ -387,6 +470,7 @@ pub fn stats_job<'a>(job_id: u64) -> Command<'a> {
         vec![Status::Ok],
         vec![Status::NotFound],
     )
+    .idempotent()
 }
 
 pub fn quit<'a>() -> Command<'a> {
@@ -406,129 +490,188 @@ mod tests {
             Duration::from_secs(10),
             Duration::from_secs(100),
         );
-        assert_eq!(cmd.build().as_str(), "put 0 10 100 4\r\nRust\r\n")
+        assert_eq!(cmd.build(), b"put 0 10 100 4\r\nRust\r\n")
+    }
+
+    #[test]
+    fn test_put_binary_body() {
+        let body = [0xff, 0x00, b'\r', b'\n', 0xfe];
+        let cmd = put(&body, 0, Duration::from_secs(0), Duration::from_secs(1));
+        assert_eq!(cmd.build(), b"put 0 0 1 5\r\n\xff\x00\r\n\xfe\r\n".to_vec())
     }
 
     #[test]
     fn test_reserve() {
         let cmd = reserve(None);
-        assert_eq!(cmd.build().as_str(), "reserve\r\n");
+        assert_eq!(cmd.build(), b"reserve\r\n");
 
         let cmd = reserve(Some(Duration::from_secs(10)));
-        assert_eq!(cmd.build().as_str(), "reserve-with-timeout 10\r\n")
+        assert_eq!(cmd.build(), b"reserve-with-timeout 10\r\n")
+    }
+
+    #[test]
+    fn test_whole_seconds() {
+        assert_eq!(
+            Duration::from_secs(0),
+            whole_seconds(Duration::from_secs(0))
+        );
+        assert_eq!(
+            Duration::from_secs(1),
+            whole_seconds(Duration::from_millis(1))
+        );
+        assert_eq!(
+            Duration::from_secs(2),
+            whole_seconds(Duration::from_millis(1500))
+        );
+        assert_eq!(
+            Duration::from_secs(3),
+            whole_seconds(Duration::from_secs(3))
+        );
+    }
+
+    #[test]
+    fn test_reserve_job() {
+        let cmd = reserve_job(10);
+        assert_eq!(cmd.build(), b"reserve-job 10\r\n");
     }
 
     #[test]
     fn test_kick() {
         let cmd = kick(100);
-        assert_eq!(cmd.build().as_str(), "kick 100\r\n");
+        assert_eq!(cmd.build(), b"kick 100\r\n");
     }
 
     #[test]
     fn test_kick_job() {
         let cmd = kick_job(1);
-        assert_eq!(cmd.build().as_str(), "kick-job 1\r\n");
+        assert_eq!(cmd.build(), b"kick-job 1\r\n");
     }
 
     #[test]
     fn test_peek_job() {
         let cmd = peek_job(1);
-        assert_eq!(cmd.build().as_str(), "peek 1\r\n");
+        assert_eq!(cmd.build(), b"peek 1\r\n");
     }
 
     #[test]
     fn test_peek_ready() {
         let cmd = peek_ready();
-        assert_eq!(cmd.build().as_str(), "peek-ready\r\n");
+        assert_eq!(cmd.build(), b"peek-ready\r\n");
     }
 
     #[test]
     fn test_peek_buried() {
         let cmd = peek_buried();
-        assert_eq!(cmd.build().as_str(), "peek-buried\r\n");
+        assert_eq!(cmd.build(), b"peek-buried\r\n");
     }
 
     #[test]
     fn test_list_tubes() {
         let cmd = tubes();
-        assert_eq!(cmd.build().as_str(), "list-tubes\r\n");
+        assert_eq!(cmd.build(), b"list-tubes\r\n");
     }
 
     #[test]
     fn test_tube_used() {
         let cmd = using();
-        assert_eq!(cmd.build().as_str(), "list-tube-used\r\n");
+        assert_eq!(cmd.build(), b"list-tube-used\r\n");
     }
 
     #[test]
     fn test_use_tube() {
         let cmd = use_tube("jobs");
-        assert_eq!(cmd.build().as_str(), "use jobs\r\n");
+        assert_eq!(cmd.build(), b"use jobs\r\n");
     }
 
     #[test]
     fn test_tubes_watched() {
         let cmd = watching();
-        assert_eq!(cmd.build().as_str(), "list-tubes-watched\r\n");
+        assert_eq!(cmd.build(), b"list-tubes-watched\r\n");
     }
 
     #[test]
     fn test_watch() {
         let cmd = watch("jobs");
-        assert_eq!(cmd.build().as_str(), "watch jobs\r\n");
+        assert_eq!(cmd.build(), b"watch jobs\r\n");
     }
 
     #[test]
     fn test_ignore() {
         let cmd = ignore("jobs");
-        assert_eq!(cmd.build().as_str(), "ignore jobs\r\n");
+        assert_eq!(cmd.build(), b"ignore jobs\r\n");
     }
 
     #[test]
     fn test_stats_tube() {
         let cmd = stats_tube("jobs");
-        assert_eq!(cmd.build().as_str(), "stats-tube jobs\r\n");
+        assert_eq!(cmd.build(), b"stats-tube jobs\r\n");
     }
 
     #[test]
     fn test_pause_tube() {
         let cmd = pause_tube("jobs", Duration::from_secs(1));
-        assert_eq!(cmd.build().as_str(), "pause-tube jobs 1\r\n");
+        assert_eq!(cmd.build(), b"pause-tube jobs 1\r\n");
     }
 
     #[test]
     fn test_delete() {
         let cmd = delete(1);
-        assert_eq!(cmd.build().as_str(), "delete 1\r\n");
+        assert_eq!(cmd.build(), b"delete 1\r\n");
     }
 
     #[test]
     fn test_release() {
         let cmd = release(100, 0, Duration::from_secs(100));
-        assert_eq!(cmd.build().as_str(), "release 100 0 100\r\n");
+        assert_eq!(cmd.build(), b"release 100 0 100\r\n");
     }
 
     #[test]
     fn test_bury() {
         let cmd = bury(100, 0);
-        assert_eq!(cmd.build().as_str(), "bury 100 0\r\n");
+        assert_eq!(cmd.build(), b"bury 100 0\r\n");
     }
 
     #[test]
     fn test_touch() {
         let cmd = touch(100);
-        assert_eq!(cmd.build().as_str(), "touch 100\r\n");
+        assert_eq!(cmd.build(), b"touch 100\r\n");
     }
 
     #[test]
     fn test_stats_job() {
         let cmd = stats_job(100);
-        assert_eq!(cmd.build().as_str(), "stats-job 100\r\n");
+        assert_eq!(cmd.build(), b"stats-job 100\r\n");
+    }
+
+    #[test]
+    fn test_status_round_trip() {
+        for s in &[
+            "OK",
+            "NOT_FOUND",
+            "DEADLINE_SOON",
+            "EXPECTED_CRLF",
+            "JOB_TOO_BIG",
+        ] {
+            assert_eq!(*s, Status::from_str(s).unwrap().to_string());
+        }
+        assert!(Status::from_str("WHATEVER").is_err());
     }
 
     #[test]
     fn test_quit() {
         let cmd = quit();
-        assert_eq!(cmd.build().as_str(), "quit\r\n");
+        assert_eq!(cmd.build(), b"quit\r\n");
+    }
+
+    #[test]
+    fn test_idempotent() {
+        assert!(peek_ready().idempotent);
+        assert!(stats_job(1).idempotent);
+        assert!(use_tube("jobs").idempotent);
+        assert!(ignore("jobs").idempotent);
+        assert!(!put(b"job", 0, Duration::from_secs(0), Duration::from_secs(1)).idempotent);
+        assert!(!reserve(None).idempotent);
+        assert!(!delete(1).idempotent);
+        assert!(!kick(1).idempotent);
     }
 }
@@ -4,6 +4,12 @@ use std::time::Duration;
 pub const DEFAULT_HOST: &str = "localhost";
 pub const DEFAULT_PORT: u16 = 11300;
 pub const DEFAULT_CONNECTION_TIMEOUT: Option<Duration> = Some(Duration::from_secs(120));
+pub const DEFAULT_TUBE: &str = "default";
 pub const DEFAULT_JOB_PRIORITY: u32 = 1 << 31;
 pub const DEFAULT_JOB_TTR: Duration = Duration::from_secs(120);
 pub const DEFAULT_JOB_DELAY: Duration = Duration::from_secs(0);
+/// Default `reserve_with_timeout` timeout of the workers and job streams.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Pause before reserving again after `DEADLINE_SOON`, which beanstalkd answers
+/// during the last second of a job TTR.
+pub const DEADLINE_SOON_BACKOFF: Duration = Duration::from_millis(200);
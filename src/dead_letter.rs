@@ -0,0 +1,254 @@
+use std::str;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::batch::PutJob;
+use crate::error::{BeanstalkcError, BeanstalkcResult};
+use crate::Beanstalkc;
+
+const DEFAULT_SUFFIX: &str = ".dead";
+// Ends the YAML document holding the metadata, the original body following it.
+const METADATA_END: &[u8] = b"\n...\n";
+
+/// `DeadLetterInfo` describes why and when a job was moved to a dead-letter tube.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DeadLetterInfo {
+    /// The tube the job was in.
+    pub tube: String,
+    /// The id the job had.
+    pub id: u64,
+    /// Why the job failed.
+    pub reason: String,
+    /// How many times the job was reserved.
+    pub reserves: u64,
+    /// How many times the job TTR expired while it was reserved.
+    pub timeouts: u64,
+    /// How many times the job was released.
+    pub releases: u64,
+    /// How many times the job was buried.
+    pub buries: u64,
+    /// When the job was moved, in seconds since the Unix epoch.
+    pub failed_at: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Target {
+    Suffix(String),
+    Tube(String),
+}
+
+/// `DeadLetter` moves permanently failed jobs to a dead-letter tube, where they
+/// don't get mixed with the buried jobs of their tube.
+///
+/// The job is put into the dead-letter tube first, with its failure metadata, and
+/// only then deleted: a failure in between leaves a duplicate rather than losing
+/// the job. Dead letters keep the priority and TTR of their job. A job whose body
+/// is close to the server's `max-job-size` cannot be dead-lettered, as the
+/// metadata makes it too big: `send` fails with `JobTooBig` and keeps the job.
+///
+/// # Example
+///
+/// ```no_run
+/// use beanstalkc::{Beanstalkc, DeadLetter};
+///
+/// let mut conn = Beanstalkc::new().connect().unwrap();
+/// let mut job = conn.reserve().unwrap().into_job().unwrap();
+/// // Failed to execute job...
+/// job.dead_letter(&DeadLetter::new(), "invalid payload").unwrap();
+///
+/// conn.watch("default.dead").unwrap();
+/// conn.ignore("default").unwrap();
+/// let mut dead = conn.reserve().unwrap().into_job().unwrap();
+/// let (info, body) = DeadLetter::decode(dead.body()).unwrap();
+/// println!("job {} failed: {}", info.id, info.reason);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeadLetter {
+    target: Target,
+}
+
+impl Default for DeadLetter {
+    fn default() -> Self {
+        DeadLetter::suffix(DEFAULT_SUFFIX)
+    }
+}
+
+impl DeadLetter {
+    /// Move jobs to their tube name followed by `.dead`.
+    pub fn new() -> DeadLetter {
+        DeadLetter::default()
+    }
+
+    /// Move jobs to their tube name followed by `suffix`.
+    pub fn suffix(suffix: &str) -> DeadLetter {
+        DeadLetter {
+            target: Target::Suffix(suffix.to_string()),
+        }
+    }
+
+    /// Move jobs to the given tube, whatever their tube.
+    pub fn tube(name: &str) -> DeadLetter {
+        DeadLetter {
+            target: Target::Tube(name.to_string()),
+        }
+    }
+
+    /// Return the dead-letter tube of the jobs of the given tube.
+    pub fn tube_for(&self, tube: &str) -> String {
+        match &self.target {
+            Target::Suffix(suffix) => format!("{}{}", tube, suffix),
+            Target::Tube(name) => name.clone(),
+        }
+    }
+
+    /// Move a job reserved by `conn` to its dead-letter tube, and return its new id.
+    pub fn send(
+        &self,
+        conn: &mut Beanstalkc,
+        job_id: u64,
+        body: &[u8],
+        reason: &str,
+    ) -> BeanstalkcResult<u64> {
+        let stats = conn.stats_job(job_id)?;
+        let info = DeadLetterInfo {
+            tube: stats.tube.clone(),
+            id: job_id,
+            reason: reason.to_string(),
+            reserves: stats.reserves,
+            timeouts: stats.timeouts,
+            releases: stats.releases,
+            buries: stats.buries,
+            failed_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |x| x.as_secs()),
+        };
+        let letter = DeadLetter::encode(&info, body)?;
+
+        let tube = self.tube_for(&stats.tube);
+        let job = PutJob::new(&letter)
+            .priority(stats.pri)
+            .ttr(stats.ttr)
+            .tube(&tube);
+        let id = conn.put_many(vec![job]).remove(0)?;
+        conn.delete(job_id)?;
+        Ok(id)
+    }
+
+    /// Return the body of a dead letter: its metadata as a YAML document, followed
+    /// by the original body.
+    pub fn encode(info: &DeadLetterInfo, body: &[u8]) -> BeanstalkcResult<Vec<u8>> {
+        let mut letter = serde_yaml::to_string(info)?.into_bytes();
+        if !letter.ends_with(b"\n") {
+            letter.push(b'\n');
+        }
+        letter.extend_from_slice(&METADATA_END[1..]);
+        letter.extend_from_slice(body);
+        Ok(letter)
+    }
+
+    /// Split the body of a dead letter into its metadata and the original body.
+    pub fn decode(letter: &[u8]) -> BeanstalkcResult<(DeadLetterInfo, &[u8])> {
+        let end = letter
+            .windows(METADATA_END.len())
+            .position(|x| x == METADATA_END)
+            .ok_or_else(|| BeanstalkcError::UnexpectedResponse("not a dead letter".to_string()))?;
+        let info = serde_yaml::from_str(str::from_utf8(&letter[..end])?)?;
+        Ok((info, &letter[end + METADATA_END.len()..]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::mock::MockServer;
+
+    #[test]
+    fn test_encode_decode() {
+        let info = DeadLetterInfo {
+            tube: "emails".to_string(),
+            id: 42,
+            reason: "line\n...\nbreaks".to_string(),
+            reserves: 5,
+            timeouts: 1,
+            releases: 4,
+            buries: 0,
+            failed_at: 1_700_000_000,
+        };
+        let body = b"\n...\n\x00\xff";
+
+        let letter = DeadLetter::encode(&info, body).unwrap();
+        let (decoded, decoded_body) = DeadLetter::decode(&letter).unwrap();
+        assert_eq!(info, decoded);
+        assert_eq!(&body[..], decoded_body);
+
+        assert!(DeadLetter::decode(b"job").is_err());
+    }
+
+    #[test]
+    fn test_tube_for() {
+        assert_eq!("emails.dead", DeadLetter::new().tube_for("emails"));
+        assert_eq!(
+            "emails-failed",
+            DeadLetter::suffix("-failed").tube_for("emails")
+        );
+        assert_eq!(
+            "graveyard",
+            DeadLetter::tube("graveyard").tube_for("emails")
+        );
+    }
+
+    #[test]
+    fn test_send() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+        conn.use_tube("emails").unwrap();
+        conn.watch("emails").unwrap();
+
+        let id = conn
+            .put(
+                b"payload",
+                7,
+                Duration::from_secs(0),
+                Duration::from_secs(30),
+            )
+            .unwrap();
+        conn.reserve().unwrap();
+        let dead = DeadLetter::new()
+            .send(&mut conn, id, b"payload", "invalid")
+            .unwrap();
+
+        assert!(conn.peek(id).is_err());
+        assert_eq!("emails", conn.using().unwrap());
+        let stats = conn.stats_job(dead).unwrap();
+        assert_eq!("emails.dead", stats.tube);
+        assert_eq!(7, stats.pri);
+        assert_eq!(Duration::from_secs(30), stats.ttr);
+
+        let letter = conn.peek(dead).unwrap().body().to_vec();
+        let (info, body) = DeadLetter::decode(&letter).unwrap();
+        assert_eq!(b"payload", body);
+        assert_eq!("emails", info.tube);
+        assert_eq!(id, info.id);
+        assert_eq!("invalid", info.reason);
+        assert_eq!(1, info.reserves);
+    }
+
+    #[test]
+    fn test_send_keeps_job_when_put_fails() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+        server.max_job_size(65535);
+
+        let id = conn.put_default(&vec![b'x'; 65500]).unwrap();
+        conn.reserve().unwrap();
+        assert!(matches!(
+            DeadLetter::new().send(&mut conn, id, &vec![b'x'; 65500], "too big"),
+            Err(BeanstalkcError::JobTooBig)
+        ));
+        assert!(conn.peek(id).is_ok());
+    }
+}
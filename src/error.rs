@@ -3,33 +3,94 @@ use std::fmt;
 use std::io;
 use std::net::AddrParseError;
 use std::num::ParseIntError;
-use std::string::FromUtf8Error;
 use std::str::Utf8Error;
+use std::string::FromUtf8Error;
+use std::sync::Arc;
+
+use crate::command::Status;
 
 #[derive(Debug, Clone)]
 pub enum BeanstalkcError {
     ConnectionError(String),
+    /// The connection URL is malformed.
+    InvalidUrl(String),
     UnexpectedResponse(String),
-    CommandFailed(String),
+    /// The server answered with an error status not covered by a dedicated variant.
+    CommandFailed(Status),
+    /// No job was reserved before the reserve timeout expired.
+    TimedOut,
+    /// A job reserved by this client is about to exceed its TTR.
+    DeadlineSoon,
+    /// The job or tube does not exist, or the job is not reserved by this client.
+    NotFound,
+    /// The server is in drain mode and no longer accepts new jobs.
+    Draining,
+    /// The job body is larger than the server's `max-job-size`.
+    JobTooBig,
+    /// The job with the given id was created but buried.
+    Buried(u64),
+    /// The job with the given id was put into the wrong tube, since its `use`
+    /// failed with the given error, and could not be deleted from it.
+    Misplaced(u64, Box<BeanstalkcError>),
+    /// Reading from or writing to the server took longer than the read or write
+    /// timeout. The connection is closed, since the response may still arrive.
+    SocketTimeout,
+    /// The underlying I/O error, shared so that the error stays cloneable.
+    IoError(Arc<io::Error>),
+}
+
+impl BeanstalkcError {
+    /// Return the kind of the underlying I/O error, if any.
+    pub fn io_kind(&self) -> Option<io::ErrorKind> {
+        match self {
+            BeanstalkcError::IoError(err) => Some(err.kind()),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for BeanstalkcError {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
         let description = match self {
             BeanstalkcError::ConnectionError(msg) => format!("Connection error: {}", msg),
+            BeanstalkcError::InvalidUrl(msg) => format!("Invalid URL: {}", msg),
             BeanstalkcError::UnexpectedResponse(msg) => format!("Unexpected response: {}", msg),
-            BeanstalkcError::CommandFailed(msg) => format!("Command failed: {}", msg),
+            BeanstalkcError::CommandFailed(status) => format!("Command failed: {}", status),
+            BeanstalkcError::TimedOut => "Timed out waiting for a job".to_string(),
+            BeanstalkcError::DeadlineSoon => "Deadline soon for a reserved job".to_string(),
+            BeanstalkcError::NotFound => "Not found".to_string(),
+            BeanstalkcError::Draining => "Server is draining".to_string(),
+            BeanstalkcError::JobTooBig => "Job too big".to_string(),
+            BeanstalkcError::Buried(id) => format!("Job {} was buried", id),
+            BeanstalkcError::Misplaced(id, err) => {
+                format!("Job {} was put into the wrong tube: {}", id, err)
+            }
+            BeanstalkcError::SocketTimeout => "Socket timed out".to_string(),
+            BeanstalkcError::IoError(err) => format!("I/O error: {}", err),
         };
 
         write!(formatter, "{}", description)
     }
 }
 
-impl Error for BeanstalkcError {}
+impl Error for BeanstalkcError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BeanstalkcError::IoError(err) => Some(err.as_ref()),
+            BeanstalkcError::Misplaced(_, err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
 
 impl From<io::Error> for BeanstalkcError {
     fn from(err: io::Error) -> Self {
-        BeanstalkcError::ConnectionError(err.to_string())
+        match err.kind() {
+            // Blocking sockets report an expired timeout as either kind, depending
+            // on the platform.
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => BeanstalkcError::SocketTimeout,
+            _ => BeanstalkcError::IoError(Arc::new(err)),
+        }
     }
 }
 
@@ -51,6 +112,12 @@ impl From<FromUtf8Error> for BeanstalkcError {
     }
 }
 
+impl From<serde_yaml::Error> for BeanstalkcError {
+    fn from(err: serde_yaml::Error) -> Self {
+        BeanstalkcError::UnexpectedResponse(err.to_string())
+    }
+}
+
 impl From<Utf8Error> for BeanstalkcError {
     fn from(err: Utf8Error) -> Self {
         BeanstalkcError::UnexpectedResponse(err.to_string())
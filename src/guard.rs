@@ -0,0 +1,185 @@
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::time::Duration;
+
+use crate::job::Job;
+
+/// `DropPolicy` tells a `JobGuard` what to do with a job that is still reserved
+/// when the guard is dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Release the job back to the ready queue with its current priority and the
+    /// given delay.
+    Release(Duration),
+    /// Bury the job with its current priority.
+    Bury,
+    /// Leave the job reserved until its TTR expires.
+    Leave,
+}
+
+/// `JobGuard` wraps a reserved `Job` and applies its `DropPolicy` if the job was
+/// neither deleted, released nor buried by the time the guard goes out of scope,
+/// e.g. because the handler returned early or panicked.
+pub struct JobGuard<'a> {
+    job: Option<Job<'a>>,
+    policy: DropPolicy,
+}
+
+impl<'a> JobGuard<'a> {
+    /// Initialize and return the `JobGuard` object.
+    pub fn new(job: Job<'a>, policy: DropPolicy) -> JobGuard<'a> {
+        JobGuard {
+            job: Some(job),
+            policy,
+        }
+    }
+
+    /// Return the drop policy of this guard.
+    pub fn policy(&self) -> DropPolicy {
+        self.policy
+    }
+
+    /// Change the drop policy of this guard.
+    pub fn set_policy(&mut self, policy: DropPolicy) {
+        self.policy = policy;
+    }
+
+    /// Return the inner job without applying the drop policy.
+    pub fn into_inner(mut self) -> Job<'a> {
+        self.job.take().unwrap()
+    }
+}
+
+impl<'a> Deref for JobGuard<'a> {
+    type Target = Job<'a>;
+
+    fn deref(&self) -> &Job<'a> {
+        self.job.as_ref().unwrap()
+    }
+}
+
+impl<'a> DerefMut for JobGuard<'a> {
+    fn deref_mut(&mut self) -> &mut Job<'a> {
+        self.job.as_mut().unwrap()
+    }
+}
+
+impl<'a> fmt::Debug for JobGuard<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JobGuard")
+            .field("job", &self.job)
+            .field("policy", &self.policy)
+            .finish()
+    }
+}
+
+impl<'a> Drop for JobGuard<'a> {
+    fn drop(&mut self) {
+        let job = match self.job.as_mut() {
+            Some(job) if job.reserved() => job,
+            _ => return,
+        };
+
+        match self.policy {
+            DropPolicy::Release(delay) => {
+                let priority = job.priority();
+                let _ = job.release(priority, delay);
+            }
+            DropPolicy::Bury => {
+                let _ = job.bury_default();
+            }
+            DropPolicy::Leave => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::mock::MockServer;
+    use crate::stats::JobState;
+
+    #[test]
+    fn test_release_on_drop() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        let id = conn
+            .put(b"job", 7, Duration::from_secs(0), Duration::from_secs(60))
+            .unwrap();
+        {
+            let job = conn.reserve().unwrap().into_job().unwrap();
+            let _guard = job.guard(DropPolicy::Release(Duration::from_secs(30)));
+        }
+
+        let stats = conn.stats_job(id).unwrap();
+        assert_eq!(JobState::Delayed, stats.state);
+        assert_eq!(7, stats.pri);
+        assert_eq!(1, stats.releases);
+    }
+
+    #[test]
+    fn test_bury_on_panic() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        let id = conn.put_default(b"job").unwrap();
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            let job = conn.reserve().unwrap().into_job().unwrap();
+            let _guard = job.guard(DropPolicy::Bury);
+            panic!("handler crashed");
+        }));
+        assert!(result.is_err());
+
+        assert_eq!(JobState::Buried, conn.stats_job(id).unwrap().state);
+    }
+
+    #[test]
+    fn test_leave_on_drop() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        let id = conn.put_default(b"job").unwrap();
+        {
+            let job = conn.reserve().unwrap().into_job().unwrap();
+            let _guard = job.guard(DropPolicy::Leave);
+        }
+
+        assert_eq!(JobState::Reserved, conn.stats_job(id).unwrap().state);
+    }
+
+    #[test]
+    fn test_no_policy_after_delete() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        let id = conn.put_default(b"job").unwrap();
+        {
+            let job = conn.reserve().unwrap().into_job().unwrap();
+            let mut guard = job.guard(DropPolicy::Bury);
+            assert_eq!(id, guard.id());
+            guard.delete().unwrap();
+        }
+
+        assert!(conn.stats_job(id).is_err());
+    }
+
+    #[test]
+    fn test_into_inner_disarms_guard() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        let id = conn.put_default(b"job").unwrap();
+        {
+            let job = conn.reserve().unwrap().into_job().unwrap();
+            let guard = job.guard(DropPolicy::Bury);
+            let job = guard.into_inner();
+            assert!(job.reserved());
+        }
+
+        assert_eq!(JobState::Reserved, conn.stats_job(id).unwrap().state);
+    }
+}
@@ -0,0 +1,76 @@
+use std::time::{Duration, Instant};
+
+use crate::stats::JobStats;
+
+// Never touch a job more often than this, whatever its TTR.
+const MIN_INTERVAL: Duration = Duration::from_millis(200);
+
+/// `Heartbeat` schedules the touches keeping a reserved job from timing out.
+///
+/// The job is first touched once half of its time left has passed, then every half
+/// TTR, so that a touch running late still lands before the server releases it.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Heartbeat {
+    interval: Duration,
+    next_touch: Instant,
+}
+
+impl Heartbeat {
+    /// Create the schedule of a job from its stats, taken while it is reserved.
+    pub(crate) fn new(stats: &JobStats) -> Heartbeat {
+        let interval = (stats.ttr / 2).max(MIN_INTERVAL);
+        Heartbeat {
+            interval,
+            next_touch: Instant::now() + (stats.time_left / 2).min(interval),
+        }
+    }
+
+    /// Return when the job should be touched next.
+    pub(crate) fn next_touch(&self) -> Instant {
+        self.next_touch
+    }
+
+    /// Record that the job was just touched, which resets its time left to its TTR.
+    pub(crate) fn touched(&mut self) {
+        self.next_touch = Instant::now() + self.interval;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::reserved_job_stats;
+
+    fn stats(ttr: u64, time_left: u64) -> JobStats {
+        JobStats {
+            ttr: Duration::from_secs(ttr),
+            time_left: Duration::from_secs(time_left),
+            ..reserved_job_stats()
+        }
+    }
+
+    fn due_in(heartbeat: &Heartbeat) -> Duration {
+        heartbeat.next_touch() - Instant::now()
+    }
+
+    #[test]
+    fn test_schedule() {
+        let mut heartbeat = Heartbeat::new(&stats(60, 20));
+        assert!(due_in(&heartbeat) <= Duration::from_secs(10));
+        assert!(due_in(&heartbeat) > Duration::from_secs(9));
+
+        heartbeat.touched();
+        assert!(due_in(&heartbeat) <= Duration::from_secs(30));
+        assert!(due_in(&heartbeat) > Duration::from_secs(29));
+    }
+
+    #[test]
+    fn test_short_ttr() {
+        let mut heartbeat = Heartbeat::new(&stats(1, 0));
+        assert!(heartbeat.next_touch() <= Instant::now());
+
+        heartbeat.touched();
+        assert!(due_in(&heartbeat) <= Duration::from_millis(500));
+        assert!(due_in(&heartbeat) > Duration::from_millis(400));
+    }
+}
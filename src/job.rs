@@ -1,10 +1,13 @@
-use std::collections::HashMap;
 use std::fmt;
 use std::time::Duration;
 
 use crate::config::DEFAULT_JOB_DELAY;
 use crate::config::DEFAULT_JOB_PRIORITY;
-use crate::error::BeanstalkcResult;
+use crate::dead_letter::DeadLetter;
+use crate::error::{BeanstalkcError, BeanstalkcResult};
+use crate::guard::{DropPolicy, JobGuard};
+use crate::retry::{RetryOutcome, RetryPolicy};
+use crate::stats::JobStats;
 use crate::Beanstalkc;
 
 /// `Job` is a simple abstraction about beanstalkd job.
@@ -16,6 +19,120 @@ pub struct Job<'a> {
     reserved: bool,
 }
 
+/// `OwnedJob` is a job detached from the connection it was fetched with.
+///
+/// It can be stored, cloned or sent to another thread, and later acted upon with
+/// `Beanstalkc::finish` or `Beanstalkc::delete/release/bury/touch`. Keep in mind
+/// beanstalkd only lets the connection that reserved a job delete, release, bury or
+/// touch it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OwnedJob {
+    id: u64,
+    body: Vec<u8>,
+    reserved: bool,
+    stats: Option<JobStats>,
+}
+
+impl fmt::Display for OwnedJob {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "OwnedJob(id: {}, reserved: {}, body: \"{:?}\")",
+            self.id, self.reserved, self.body
+        )
+    }
+}
+
+impl OwnedJob {
+    /// Initialize and return the `OwnedJob` object.
+    pub fn new(job_id: u64, body: Vec<u8>, reserved: bool) -> OwnedJob {
+        OwnedJob {
+            id: job_id,
+            body,
+            reserved,
+            stats: None,
+        }
+    }
+
+    /// Return job id.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Return job body.
+    pub fn body(&self) -> &[u8] {
+        &self.body[..]
+    }
+
+    /// Consume the job and return its body.
+    pub fn into_body(self) -> Vec<u8> {
+        self.body
+    }
+
+    /// Return job reserving status at the time it was detached.
+    pub fn reserved(&self) -> bool {
+        self.reserved
+    }
+
+    /// Return the job stats snapshot taken when it was detached, if any.
+    pub fn stats(&self) -> Option<&JobStats> {
+        self.stats.as_ref()
+    }
+}
+
+/// `Outcome` tells `Beanstalkc::finish` what to do with a reserved job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Delete the job.
+    Delete,
+    /// Release the job back to the ready queue.
+    Release { priority: u32, delay: Duration },
+    /// Bury the job.
+    Bury { priority: u32 },
+}
+
+/// `ReserveOutcome` is what the server answered to a reserve command.
+#[derive(Debug)]
+pub enum ReserveOutcome<'a> {
+    /// A job was reserved.
+    Job(Job<'a>),
+    /// A job reserved by this client is about to exceed its TTR. Delete, release or
+    /// touch it before reserving again.
+    DeadlineSoon,
+    /// No job became available before the reserve timeout expired.
+    TimedOut,
+}
+
+impl<'a> ReserveOutcome<'a> {
+    /// Return the reserved job, if any.
+    pub fn job(self) -> Option<Job<'a>> {
+        match self {
+            ReserveOutcome::Job(job) => Some(job),
+            _ => None,
+        }
+    }
+
+    /// Return the reserved job, turning `DeadlineSoon` and `TimedOut` into errors.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use beanstalkc::Beanstalkc;
+    ///
+    /// let mut conn = Beanstalkc::new().connect().unwrap();
+    ///
+    /// let mut job = conn.reserve().unwrap().into_job().unwrap();
+    /// job.delete().unwrap();
+    /// ```
+    pub fn into_job(self) -> BeanstalkcResult<Job<'a>> {
+        match self {
+            ReserveOutcome::Job(job) => Ok(job),
+            ReserveOutcome::DeadlineSoon => Err(BeanstalkcError::DeadlineSoon),
+            ReserveOutcome::TimedOut => Err(BeanstalkcError::TimedOut),
+        }
+    }
+}
+
 impl<'a> fmt::Display for Job<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         write!(
@@ -28,7 +145,7 @@ impl<'a> fmt::Display for Job<'a> {
 
 impl<'a> Job<'a> {
     /// Initialize and return the `Job` object.
-    pub fn new(conn: &'a mut Beanstalkc, job_id: u64, body: Vec<u8>, reserved: bool) -> Job {
+    pub fn new(conn: &'a mut Beanstalkc, job_id: u64, body: Vec<u8>, reserved: bool) -> Job<'a> {
         Job {
             conn,
             id: job_id,
@@ -61,7 +178,7 @@ impl<'a> Job<'a> {
     ///
     /// let mut conn = Beanstalkc::new().connect().unwrap();
     ///
-    /// let mut job = conn.reserve().unwrap();
+    /// let mut job = conn.reserve().unwrap().into_job().unwrap();
     /// job.delete().unwrap();
     /// ```
     pub fn delete(&mut self) -> BeanstalkcResult<()> {
@@ -79,7 +196,7 @@ impl<'a> Job<'a> {
     ///
     /// let mut conn = Beanstalkc::new().connect().unwrap();
     ///
-    /// let mut job = conn.reserve().unwrap();
+    /// let mut job = conn.reserve().unwrap().into_job().unwrap();
     /// job.release_default().unwrap();
     /// ```
     pub fn release_default(&mut self) -> BeanstalkcResult<()> {
@@ -97,7 +214,7 @@ impl<'a> Job<'a> {
     ///
     /// let mut conn = Beanstalkc::new().connect().unwrap();
     ///
-    /// let mut job = conn.reserve().unwrap();
+    /// let mut job = conn.reserve().unwrap().into_job().unwrap();
     /// job.release(0, Duration::from_secs(0)).unwrap();
     /// ```
     pub fn release(&mut self, priority: u32, delay: Duration) -> BeanstalkcResult<()> {
@@ -110,6 +227,45 @@ impl<'a> Job<'a> {
         Ok(())
     }
 
+    /// Retry this failed job as told by the policy: release it after a backoff delay,
+    /// or bury it or move it to its dead-letter tube once its attempts are exhausted.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use beanstalkc::{Beanstalkc, RetryPolicy};
+    ///
+    /// let mut conn = Beanstalkc::new().connect().unwrap();
+    ///
+    /// let mut job = conn.reserve().unwrap().into_job().unwrap();
+    /// job.retry(&RetryPolicy::exponential(Duration::from_secs(1))).unwrap();
+    /// ```
+    pub fn retry(&mut self, policy: &RetryPolicy) -> BeanstalkcResult<RetryOutcome> {
+        let outcome = policy.apply(self.conn, self.id, &self.body)?;
+        self.reserved = false;
+        Ok(outcome)
+    }
+
+    /// Move this failed job to its dead-letter tube with the given reason, and
+    /// return its new id.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use beanstalkc::{Beanstalkc, DeadLetter};
+    ///
+    /// let mut conn = Beanstalkc::new().connect().unwrap();
+    ///
+    /// let mut job = conn.reserve().unwrap().into_job().unwrap();
+    /// job.dead_letter(&DeadLetter::new(), "invalid payload").unwrap();
+    /// ```
+    pub fn dead_letter(&mut self, dead_letter: &DeadLetter, reason: &str) -> BeanstalkcResult<u64> {
+        let id = dead_letter.send(self.conn, self.id, &self.body, reason)?;
+        self.reserved = false;
+        Ok(id)
+    }
+
     /// Bury this job with default priority.
     ///
     /// # Example
@@ -120,7 +276,7 @@ impl<'a> Job<'a> {
     ///
     /// let mut conn = Beanstalkc::new().connect().unwrap();
     ///
-    /// let mut job = conn.reserve().unwrap();
+    /// let mut job = conn.reserve().unwrap().into_job().unwrap();
     /// job.bury_default().unwrap();
     /// ```
     pub fn bury_default(&mut self) -> BeanstalkcResult<()> {
@@ -138,7 +294,7 @@ impl<'a> Job<'a> {
     ///
     /// let mut conn = Beanstalkc::new().connect().unwrap();
     ///
-    /// let mut job = conn.reserve().unwrap();
+    /// let mut job = conn.reserve().unwrap().into_job().unwrap();
     /// job.bury(1024).unwrap();
     /// ```
     pub fn bury(&mut self, priority: u32) -> BeanstalkcResult<()> {
@@ -178,7 +334,7 @@ impl<'a> Job<'a> {
     ///
     /// let mut conn = Beanstalkc::new().connect().unwrap();
     ///
-    /// let mut job = conn.reserve().unwrap();
+    /// let mut job = conn.reserve().unwrap().into_job().unwrap();
     /// job.touch().unwrap();
     /// ```
     pub fn touch(&mut self) -> BeanstalkcResult<()> {
@@ -189,7 +345,7 @@ impl<'a> Job<'a> {
         self.conn.touch(self.id)
     }
 
-    /// Return a dict of statistical information about this job.
+    /// Return statistical information about this job.
     ///
     /// # Example
     ///
@@ -203,16 +359,77 @@ impl<'a> Job<'a> {
     /// let job_stats = job.stats().unwrap();
     /// dbg!(job_stats);
     /// ```
-    pub fn stats(&mut self) -> BeanstalkcResult<HashMap<String, String>> {
+    pub fn stats(&mut self) -> BeanstalkcResult<JobStats> {
         self.conn.stats_job(self.id)
     }
 
+    /// Detach this job from the connection, so that it can outlive the borrow.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::thread;
+    /// use beanstalkc::{Beanstalkc, Outcome};
+    ///
+    /// let mut conn = Beanstalkc::new().connect().unwrap();
+    ///
+    /// let job = conn.reserve().unwrap().into_job().unwrap().detach();
+    /// let job = thread::spawn(move || {
+    ///     // Execute job...
+    ///     job
+    /// })
+    /// .join()
+    /// .unwrap();
+    /// conn.finish(job, Outcome::Delete).unwrap();
+    /// ```
+    pub fn detach(self) -> OwnedJob {
+        OwnedJob::new(self.id, self.body, self.reserved)
+    }
+
+    /// Detach this job from the connection, taking a snapshot of its stats first.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use beanstalkc::Beanstalkc;
+    ///
+    /// let mut conn = Beanstalkc::new().connect().unwrap();
+    ///
+    /// let job = conn.reserve().unwrap().into_job().unwrap();
+    /// let job = job.detach_with_stats().unwrap();
+    /// dbg!(job.stats().unwrap().ttr);
+    /// ```
+    pub fn detach_with_stats(mut self) -> BeanstalkcResult<OwnedJob> {
+        let stats = self.stats()?;
+        let mut job = self.detach();
+        job.stats = Some(stats);
+        Ok(job)
+    }
+
+    /// Wrap this job in a `JobGuard` which applies `policy` if the job is still
+    /// reserved when the guard is dropped.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use beanstalkc::{Beanstalkc, DropPolicy};
+    ///
+    /// let mut conn = Beanstalkc::new().connect().unwrap();
+    ///
+    /// let job = conn.reserve().unwrap().into_job().unwrap();
+    /// let mut job = job.guard(DropPolicy::Release(Duration::from_secs(10)));
+    /// // Execute job, returning early or panicking releases it...
+    /// job.delete().unwrap();
+    /// ```
+    pub fn guard(self, policy: DropPolicy) -> JobGuard<'a> {
+        JobGuard::new(self, policy)
+    }
+
     /// Return the job priority from this job stats. If not found, return the `DEFAULT_JOB_PRIORITY`.
-    fn priority(&mut self) -> u32 {
-        let stats = self.stats().unwrap_or_default();
-        stats
-            .get("pri")
-            .map(|x| x.parse().unwrap_or(DEFAULT_JOB_PRIORITY))
+    pub(crate) fn priority(&mut self) -> u32 {
+        self.stats()
+            .map(|stats| stats.pri)
             .unwrap_or(DEFAULT_JOB_PRIORITY)
     }
 }
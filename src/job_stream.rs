@@ -0,0 +1,246 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::{ready, Stream};
+use tokio::time;
+
+use crate::async_client::{AsyncBeanstalkc, AsyncReserveOutcome};
+use crate::command;
+use crate::config::{DEADLINE_SOON_BACKOFF, DEFAULT_POLL_INTERVAL};
+use crate::error::BeanstalkcResult;
+use crate::job::OwnedJob;
+use crate::shutdown::ShutdownHandle;
+
+type Reservation =
+    Pin<Box<dyn Future<Output = (AsyncBeanstalkc, BeanstalkcResult<Option<OwnedJob>>)> + Send>>;
+
+/// `JobStream` yields the jobs reserved from a set of tubes, as returned by
+/// `AsyncBeanstalkc::jobs`.
+///
+/// It reserves with `reserve_with_timeout` under the hood, retrying on `TIMED_OUT`
+/// and, after a short pause, on `DEADLINE_SOON`. The stream ends once its
+/// `ShutdownHandle` is triggered, or after yielding an error.
+///
+/// Polling it is cancel safe: a reservation in flight when `next()` is dropped is
+/// resumed by the following call, so no job gets lost.
+///
+/// # Example
+///
+/// ```no_run
+/// # async fn run() -> beanstalkc::BeanstalkcResult<()> {
+/// use beanstalkc::AsyncBeanstalkc;
+/// use futures::StreamExt;
+///
+/// let conn = AsyncBeanstalkc::new().connect().await?;
+/// let mut jobs = conn.jobs(&["emails", "reports"]).await?;
+///
+/// while let Some(job) = jobs.next().await {
+///     let job = job?;
+///     // Execute job...
+///     jobs.connection().unwrap().delete(job.id()).await?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct JobStream {
+    conn: Option<AsyncBeanstalkc>,
+    reservation: Option<Reservation>,
+    poll_interval: Duration,
+    shutdown: ShutdownHandle,
+    done: bool,
+}
+
+impl JobStream {
+    pub(crate) fn new(conn: AsyncBeanstalkc) -> JobStream {
+        JobStream {
+            conn: Some(conn),
+            reservation: None,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            shutdown: ShutdownHandle::new(),
+            done: false,
+        }
+    }
+
+    /// Set the `reserve_with_timeout` timeout, which bounds how long a shutdown
+    /// request may wait for the reservation in flight. Default is `1s`.
+    ///
+    /// The timeout is rounded up to whole seconds, the resolution of the protocol,
+    /// rather than truncated to `0s`, which reserves without waiting.
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = command::whole_seconds(interval);
+        self
+    }
+
+    /// Return a handle that ends the stream once the reservation in flight, if any,
+    /// completes.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Return the connection to delete, release, bury or touch the yielded jobs,
+    /// unless a reservation is in flight.
+    pub fn connection(&mut self) -> Option<&mut AsyncBeanstalkc> {
+        self.conn.as_mut()
+    }
+
+    /// Return the connection, unless a reservation is in flight.
+    pub fn into_connection(self) -> Option<AsyncBeanstalkc> {
+        self.conn
+    }
+}
+
+impl Stream for JobStream {
+    type Item = BeanstalkcResult<OwnedJob>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if this.reservation.is_none() {
+                if this.done || this.shutdown.is_shutdown() {
+                    return Poll::Ready(None);
+                }
+                let mut conn = this.conn.take().unwrap();
+                let timeout = this.poll_interval;
+                this.reservation = Some(Box::pin(async move {
+                    let result = reserve_next(&mut conn, timeout).await;
+                    (conn, result)
+                }));
+            }
+
+            let (conn, result) = ready!(this.reservation.as_mut().unwrap().as_mut().poll(cx));
+            this.reservation = None;
+            this.conn = Some(conn);
+            match result {
+                Ok(Some(job)) => return Poll::Ready(Some(Ok(job))),
+                Ok(None) => continue,
+                Err(e) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(e)));
+                }
+            }
+        }
+    }
+}
+
+/// Reserve the next job, returning `None` when there is none to yield yet.
+async fn reserve_next(
+    conn: &mut AsyncBeanstalkc,
+    timeout: Duration,
+) -> BeanstalkcResult<Option<OwnedJob>> {
+    match conn.reserve_with_timeout(timeout).await? {
+        AsyncReserveOutcome::Job(job) => Ok(Some(job)),
+        AsyncReserveOutcome::TimedOut => Ok(None),
+        AsyncReserveOutcome::DeadlineSoon => {
+            time::sleep(DEADLINE_SOON_BACKOFF).await;
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::mock::MockServer;
+
+    #[tokio::test]
+    async fn test_jobs_from_watched_tubes() {
+        let server = MockServer::start();
+        let mut producer = server.connect();
+        producer.use_tube("a").unwrap();
+        let first = producer.put_default(b"first").unwrap();
+        producer.use_tube("c").unwrap();
+        producer.put_default(b"ignored").unwrap();
+        producer.use_tube("b").unwrap();
+        let second = producer.put_default(b"second").unwrap();
+
+        let mut jobs = server
+            .connect_async()
+            .await
+            .jobs(&["a", "b"])
+            .await
+            .unwrap()
+            .poll_interval(Duration::from_secs(0));
+        for id in &[first, second] {
+            let job = jobs.next().await.unwrap().unwrap();
+            assert_eq!(*id, job.id());
+            jobs.connection().unwrap().delete(job.id()).await.unwrap();
+        }
+
+        let conn = jobs.connection().unwrap();
+        assert_eq!(
+            vec!["a".to_string(), "b".to_string()],
+            conn.watching().await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_ends_stream() {
+        let server = MockServer::start();
+        let mut jobs = server.connect_async().await.jobs(&["a"]).await.unwrap();
+
+        let shutdown = jobs.shutdown_handle();
+        tokio::spawn(async move {
+            time::sleep(Duration::from_millis(100)).await;
+            shutdown.shutdown();
+        });
+        assert!(jobs.next().await.is_none());
+        assert!(jobs.into_connection().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_next_keeps_reservation() {
+        let server = MockServer::start();
+        let mut jobs = server
+            .connect_async()
+            .await
+            .jobs(&["default"])
+            .await
+            .unwrap();
+
+        assert!(time::timeout(Duration::from_millis(50), jobs.next())
+            .await
+            .is_err());
+        assert!(jobs.connection().is_none());
+
+        let id = server.connect().put_default(b"job").unwrap();
+        assert_eq!(id, jobs.next().await.unwrap().unwrap().id());
+    }
+
+    #[tokio::test]
+    async fn test_deadline_soon_is_retried() {
+        let server = MockServer::start();
+        let id = server
+            .connect()
+            .put(b"job", 0, Duration::from_secs(0), Duration::from_secs(1))
+            .unwrap();
+
+        let mut jobs = server
+            .connect_async()
+            .await
+            .jobs(&["default"])
+            .await
+            .unwrap();
+        assert_eq!(id, jobs.next().await.unwrap().unwrap().id());
+        // The job is never finished: it comes back once its TTR expires.
+        assert_eq!(id, jobs.next().await.unwrap().unwrap().id());
+    }
+
+    #[tokio::test]
+    async fn test_error_ends_stream() {
+        let server = MockServer::start();
+        let mut jobs = server
+            .connect_async()
+            .await
+            .jobs(&["default"])
+            .await
+            .unwrap();
+
+        server.drop_connections();
+        assert!(jobs.next().await.unwrap().is_err());
+        assert!(jobs.next().await.is_none());
+    }
+}
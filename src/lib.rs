@@ -36,18 +36,60 @@
 //!
 //! conn.watch("jobs").unwrap();
 //!
-//! let mut job = conn.reserve().unwrap();
+//! let mut job = conn.reserve().unwrap().into_job().unwrap();
 //! // execute job here...
 //! job.delete().unwrap();
 //! ```
+#[cfg(feature = "tokio")]
+pub use crate::async_client::{AsyncBeanstalkc, AsyncReserveOutcome};
+pub use crate::batch::PutJob;
 pub use crate::beanstalkc::Beanstalkc;
+pub use crate::command::Status;
+pub use crate::dead_letter::{DeadLetter, DeadLetterInfo};
 pub use crate::error::{BeanstalkcError, BeanstalkcResult};
-pub use crate::job::Job;
+pub use crate::guard::{DropPolicy, JobGuard};
+pub use crate::job::{Job, Outcome, OwnedJob, ReserveOutcome};
+#[cfg(feature = "tokio")]
+pub use crate::job_stream::JobStream;
+pub use crate::pipeline::{Pipeline, Reply};
+pub use crate::pool::{Pool, PoolBuilder, PooledConnection};
+pub use crate::reconnect::ReconnectPolicy;
+pub use crate::retry::{Backoff, Exhausted, RetryOutcome, RetryPolicy};
+pub use crate::shard::{JobHandle, ShardedClient, ShardedJob, ShardedReserveOutcome};
+pub use crate::shutdown::ShutdownHandle;
+pub use crate::stats::{JobState, JobStats, ServerStats, TubeStats};
+#[cfg(feature = "tls")]
+pub use crate::tls::TlsConfig;
+pub use crate::worker::{Action, Handler, Worker};
 
+#[cfg(feature = "tokio")]
+mod async_client;
+mod batch;
 mod beanstalkc;
+#[cfg(feature = "tokio")]
+mod codec;
 mod command;
 mod config;
+mod dead_letter;
 mod error;
+mod guard;
+mod heartbeat;
 mod job;
+#[cfg(feature = "tokio")]
+mod job_stream;
+#[cfg(test)]
+mod mock;
+mod pipeline;
+mod pool;
+mod reconnect;
 mod request;
 mod response;
+mod retry;
+mod shard;
+mod shutdown;
+mod stats;
+#[cfg(feature = "tls")]
+mod tls;
+mod transport;
+mod url;
+mod worker;
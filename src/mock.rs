@@ -0,0 +1,800 @@
+//! A tiny in-memory beanstalkd stand-in used by the unit tests.
+//!
+//! Only the parts of the protocol exercised by the tests are implemented, but
+//! those follow protocol.md closely enough that the client cannot tell the
+//! difference.
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::stats::{JobState, JobStats};
+#[cfg(feature = "tokio")]
+use crate::AsyncBeanstalkc;
+use crate::Beanstalkc;
+
+const DEFAULT_TUBE: &str = "default";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Ready,
+    Delayed(Instant),
+    Reserved(u64, Instant),
+    Buried,
+}
+
+#[derive(Debug)]
+struct MockJob {
+    tube: String,
+    priority: u32,
+    delay: u64,
+    ttr: u64,
+    body: Vec<u8>,
+    state: State,
+    created: Instant,
+    reserves: u64,
+    timeouts: u64,
+    releases: u64,
+    buries: u64,
+    kicks: u64,
+}
+
+#[derive(Debug, Default)]
+struct Store {
+    next_id: u64,
+    jobs: BTreeMap<u64, MockJob>,
+}
+
+impl Store {
+    /// Move delayed jobs whose delay has passed and reserved jobs whose TTR has
+    /// expired back to the ready queue.
+    fn tick(&mut self) {
+        let now = Instant::now();
+        for job in self.jobs.values_mut() {
+            match job.state {
+                State::Delayed(at) if at <= now => job.state = State::Ready,
+                State::Reserved(_, deadline) if deadline <= now => {
+                    job.state = State::Ready;
+                    job.timeouts += 1;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn next_ready(&self, tubes: &[String]) -> Option<u64> {
+        self.jobs
+            .iter()
+            .filter(|(_, job)| job.state == State::Ready && tubes.contains(&job.tube))
+            .min_by_key(|(id, job)| (job.priority, **id))
+            .map(|(id, _)| *id)
+    }
+
+    /// Return the `current-jobs-*` stats lines, optionally for one tube only.
+    fn counts(&self, tube: Option<&str>) -> String {
+        let (mut urgent, mut ready, mut reserved, mut delayed, mut buried) = (0, 0, 0, 0, 0);
+        for job in self.jobs.values() {
+            if tube.is_some_and(|t| t != job.tube) {
+                continue;
+            }
+            match job.state {
+                State::Ready => {
+                    ready += 1;
+                    if job.priority < 1024 {
+                        urgent += 1;
+                    }
+                }
+                State::Delayed(_) => delayed += 1,
+                State::Reserved(..) => reserved += 1,
+                State::Buried => buried += 1,
+            }
+        }
+        format!(
+            "current-jobs-urgent: {}\ncurrent-jobs-ready: {}\ncurrent-jobs-reserved: {}\n\
+             current-jobs-delayed: {}\ncurrent-jobs-buried: {}\n",
+            urgent, ready, reserved, delayed, buried
+        )
+    }
+
+    /// Whether a job reserved by `conn_id` is within its one second safety margin.
+    fn deadline_soon(&self, conn_id: u64) -> bool {
+        let margin = Instant::now() + Duration::from_secs(1);
+        self.jobs.values().any(|job| match job.state {
+            State::Reserved(owner, deadline) => owner == conn_id && deadline <= margin,
+            _ => false,
+        })
+    }
+
+    /// Whether `conn_id` still holds the reservation of job `id`, once expired TTRs
+    /// are taken into account.
+    fn reserved_by(&mut self, conn_id: u64, id: u64) -> bool {
+        self.tick();
+        match self.jobs.get(&id).map(|job| job.state) {
+            Some(State::Reserved(owner, _)) => owner == conn_id,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    store: Mutex<Store>,
+    changed: Condvar,
+    next_conn_id: AtomicU64,
+    bury_puts: AtomicBool,
+    reject_deletes: AtomicBool,
+    reject_touches: AtomicBool,
+    // Zero accepts bodies of any size.
+    max_job_size: AtomicUsize,
+    // Number of commands answered before hanging up once.
+    hang_up_after: Mutex<Option<usize>>,
+    generation: AtomicU64,
+}
+
+/// `MockServer` accepts any number of connections on a random local port, or on
+/// a Unix socket in the temporary directory.
+pub struct MockServer {
+    port: u16,
+    path: Option<PathBuf>,
+    shared: Arc<Shared>,
+}
+
+impl MockServer {
+    /// Bind to a random local port and serve connections in the background.
+    pub fn start() -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let shared = Arc::new(Shared::default());
+        let server = MockServer {
+            port,
+            path: None,
+            shared: shared.clone(),
+        };
+
+        thread::spawn(move || {
+            // Replies are written one at a time: without TCP_NODELAY, delayed ACKs
+            // would stall every chunk of a pipeline.
+            let incoming = listener
+                .incoming()
+                .map(|stream| stream.and_then(|s| s.set_nodelay(true).map(|_| s)));
+            serve_all(shared, incoming)
+        });
+
+        server
+    }
+
+    /// Bind to a fresh Unix socket and serve connections in the background.
+    #[cfg(unix)]
+    pub fn start_unix() -> MockServer {
+        static NEXT_SOCKET: AtomicU64 = AtomicU64::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "beanstalkc-mock-{}-{}.sock",
+            std::process::id(),
+            NEXT_SOCKET.fetch_add(1, Ordering::SeqCst)
+        ));
+        let listener = UnixListener::bind(&path).unwrap();
+        let shared = Arc::new(Shared::default());
+        let server = MockServer {
+            port: 0,
+            path: Some(path),
+            shared: shared.clone(),
+        };
+
+        thread::spawn(move || serve_all(shared, listener.incoming()));
+
+        server
+    }
+
+    /// Bind to a random local port and serve TLS connections in the background.
+    #[cfg(feature = "tls")]
+    pub fn start_tls(config: Arc<rustls::ServerConfig>) -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let shared = Arc::new(Shared::default());
+        let server = MockServer {
+            port,
+            path: None,
+            shared: shared.clone(),
+        };
+
+        thread::spawn(move || {
+            let incoming = listener.incoming().map(|stream| {
+                let conn = rustls::ServerConnection::new(config.clone()).unwrap();
+                stream.map(|s| rustls::StreamOwned::new(conn, s))
+            });
+            serve_all(shared, incoming)
+        });
+
+        server
+    }
+
+    /// Answer every following put with `BURIED <id>`, as beanstalkd does when it
+    /// runs out of memory for its priority queue.
+    pub fn bury_puts(&self, bury: bool) {
+        self.shared.bury_puts.store(bury, Ordering::SeqCst);
+    }
+
+    /// Answer every following touch with `NOT_FOUND`, as if the jobs had timed out
+    /// and been reserved by another client in the meantime.
+    pub fn reject_touches(&self, reject: bool) {
+        self.shared.reject_touches.store(reject, Ordering::SeqCst);
+    }
+
+    /// Answer `JOB_TOO_BIG` to the following puts of bodies larger than `size`,
+    /// like `beanstalkd -z` does.
+    pub fn max_job_size(&self, size: usize) {
+        self.shared.max_job_size.store(size, Ordering::SeqCst);
+    }
+
+    /// Answer every following delete with `NOT_FOUND`, as if the jobs had been
+    /// reserved by another client in the meantime.
+    pub fn reject_deletes(&self, reject: bool) {
+        self.shared.reject_deletes.store(reject, Ordering::SeqCst);
+    }
+
+    /// Answer `count` more commands, then hang up on the connection sending the
+    /// next one, as if the server crashed in the middle of a pipeline.
+    pub fn hang_up_after(&self, count: usize) {
+        *self.shared.hang_up_after.lock().unwrap() = Some(count);
+    }
+
+    /// Hang up every open connection as soon as it sends its next command, as if
+    /// the server restarted.
+    pub fn drop_connections(&self) {
+        self.shared.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Return a connected client.
+    pub fn connect(&self) -> Beanstalkc {
+        match &self.path {
+            Some(path) => Beanstalkc::unix(path).connect().unwrap(),
+            None => Beanstalkc::new()
+                .host("127.0.0.1")
+                .port(self.port)
+                .connect()
+                .unwrap(),
+        }
+    }
+
+    /// Return a connected async client.
+    #[cfg(feature = "tokio")]
+    pub async fn connect_async(&self) -> AsyncBeanstalkc {
+        AsyncBeanstalkc::new()
+            .host("127.0.0.1")
+            .port(self.port)
+            .connect()
+            .await
+            .unwrap()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        if let Some(path) = &self.path {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Return the stats of a job just reserved from the default tube, with a `60s`
+/// TTR, for the tests of code reading them. Other values can be set with the
+/// struct update syntax.
+pub fn reserved_job_stats() -> JobStats {
+    JobStats {
+        id: 1,
+        tube: DEFAULT_TUBE.to_string(),
+        state: JobState::Reserved,
+        pri: 0,
+        age: Duration::from_secs(0),
+        delay: Duration::from_secs(0),
+        ttr: Duration::from_secs(60),
+        time_left: Duration::from_secs(60),
+        file: 0,
+        reserves: 1,
+        timeouts: 0,
+        releases: 0,
+        buries: 0,
+        kicks: 0,
+    }
+}
+
+struct Session {
+    id: u64,
+    generation: u64,
+    using: String,
+    watching: Vec<String>,
+}
+
+fn serve_all<S, I>(shared: Arc<Shared>, incoming: I)
+where
+    S: Read + Write + Send + 'static,
+    I: Iterator<Item = io::Result<S>>,
+{
+    for stream in incoming {
+        let stream = match stream {
+            Ok(s) => s,
+            Err(_) => break,
+        };
+        let shared = shared.clone();
+        thread::spawn(move || serve(shared, stream));
+    }
+}
+
+fn serve<S: Read + Write>(shared: Arc<Shared>, stream: S) {
+    let mut session = Session {
+        id: shared.next_conn_id.fetch_add(1, Ordering::SeqCst),
+        generation: shared.generation.load(Ordering::SeqCst),
+        using: DEFAULT_TUBE.to_string(),
+        watching: vec![DEFAULT_TUBE.to_string()],
+    };
+    let mut reader = BufReader::new(stream);
+
+    loop {
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        if shared.generation.load(Ordering::SeqCst) != session.generation {
+            break;
+        }
+        {
+            let mut hang_up_after = shared.hang_up_after.lock().unwrap();
+            match hang_up_after.as_mut() {
+                Some(0) => {
+                    *hang_up_after = None;
+                    break;
+                }
+                Some(count) => *count -= 1,
+                None => {}
+            }
+        }
+        let parts: Vec<String> = line.split_whitespace().map(String::from).collect();
+        if parts.first().map(String::as_str) == Some("quit") {
+            break;
+        }
+
+        let reply = handle(&shared, &mut session, &parts, &mut reader);
+        let writer = reader.get_mut();
+        if writer
+            .write_all(&reply)
+            .and_then(|_| writer.flush())
+            .is_err()
+        {
+            break;
+        }
+    }
+
+    // Jobs reserved by a closed connection go back to the ready queue.
+    let mut store = shared.store.lock().unwrap();
+    for job in store.jobs.values_mut() {
+        if let State::Reserved(owner, _) = job.state {
+            if owner == session.id {
+                job.state = State::Ready;
+            }
+        }
+    }
+    shared.changed.notify_all();
+}
+
+fn arg<T: std::str::FromStr>(parts: &[String], index: usize) -> Option<T> {
+    parts.get(index).and_then(|x| x.parse().ok())
+}
+
+fn with_body(header: String, body: &[u8]) -> Vec<u8> {
+    let mut reply = format!("{} {}\r\n", header, body.len()).into_bytes();
+    reply.extend_from_slice(body);
+    reply.extend_from_slice(b"\r\n");
+    reply
+}
+
+fn yaml_list(items: &[String]) -> Vec<u8> {
+    let mut yaml = String::from("---\n");
+    for item in items {
+        yaml.push_str(&format!("- {}\n", item));
+    }
+    with_body("OK".to_string(), yaml.as_bytes())
+}
+
+fn handle<R: BufRead>(
+    shared: &Shared,
+    session: &mut Session,
+    parts: &[String],
+    reader: &mut R,
+) -> Vec<u8> {
+    let name = parts.first().map(String::as_str).unwrap_or("");
+    match name {
+        "put" => {
+            let (priority, delay, ttr, bytes) = match (
+                arg::<u32>(parts, 1),
+                arg::<u64>(parts, 2),
+                arg::<u64>(parts, 3),
+                arg::<usize>(parts, 4),
+            ) {
+                (Some(p), Some(d), Some(t), Some(b)) => (p, d, t, b),
+                _ => return b"BAD_FORMAT\r\n".to_vec(),
+            };
+            let mut body = vec![0; bytes + 2];
+            if reader.read_exact(&mut body).is_err() {
+                return b"BAD_FORMAT\r\n".to_vec();
+            }
+            if !body.ends_with(b"\r\n") {
+                return b"EXPECTED_CRLF\r\n".to_vec();
+            }
+            body.truncate(bytes);
+            let max_job_size = shared.max_job_size.load(Ordering::SeqCst);
+            if max_job_size > 0 && bytes > max_job_size {
+                return b"JOB_TOO_BIG\r\n".to_vec();
+            }
+
+            let buried = shared.bury_puts.load(Ordering::SeqCst);
+            let mut store = shared.store.lock().unwrap();
+            store.next_id += 1;
+            let id = store.next_id;
+            let now = Instant::now();
+            store.jobs.insert(
+                id,
+                MockJob {
+                    tube: session.using.clone(),
+                    priority,
+                    delay,
+                    ttr: ttr.max(1),
+                    body,
+                    state: if buried {
+                        State::Buried
+                    } else if delay > 0 {
+                        State::Delayed(now + Duration::from_secs(delay))
+                    } else {
+                        State::Ready
+                    },
+                    created: now,
+                    reserves: 0,
+                    timeouts: 0,
+                    releases: 0,
+                    buries: 0,
+                    kicks: 0,
+                },
+            );
+            shared.changed.notify_all();
+            if buried {
+                format!("BURIED {}\r\n", id).into_bytes()
+            } else {
+                format!("INSERTED {}\r\n", id).into_bytes()
+            }
+        }
+        "use" => match parts.get(1) {
+            Some(tube) => {
+                session.using = tube.clone();
+                format!("USING {}\r\n", tube).into_bytes()
+            }
+            None => b"BAD_FORMAT\r\n".to_vec(),
+        },
+        "watch" => match parts.get(1) {
+            Some(tube) => {
+                if !session.watching.contains(tube) {
+                    session.watching.push(tube.clone());
+                }
+                format!("WATCHING {}\r\n", session.watching.len()).into_bytes()
+            }
+            None => b"BAD_FORMAT\r\n".to_vec(),
+        },
+        "ignore" => match parts.get(1) {
+            Some(tube) => {
+                if session.watching.len() == 1 && session.watching[0] == *tube {
+                    return b"NOT_IGNORED\r\n".to_vec();
+                }
+                session.watching.retain(|t| t != tube);
+                format!("WATCHING {}\r\n", session.watching.len()).into_bytes()
+            }
+            None => b"BAD_FORMAT\r\n".to_vec(),
+        },
+        "list-tube-used" => format!("USING {}\r\n", session.using).into_bytes(),
+        "list-tubes-watched" => yaml_list(&session.watching),
+        "list-tubes" => {
+            let store = shared.store.lock().unwrap();
+            let mut tubes = vec![DEFAULT_TUBE.to_string()];
+            for job in store.jobs.values() {
+                if !tubes.contains(&job.tube) {
+                    tubes.push(job.tube.clone());
+                }
+            }
+            yaml_list(&tubes)
+        }
+        "reserve" | "reserve-with-timeout" => {
+            let timeout = if name == "reserve" {
+                None
+            } else {
+                match arg::<u64>(parts, 1) {
+                    Some(t) => Some(Duration::from_secs(t)),
+                    None => return b"BAD_FORMAT\r\n".to_vec(),
+                }
+            };
+            reserve(shared, session, timeout)
+        }
+        "reserve-job" => {
+            let id = arg::<u64>(parts, 1).unwrap_or(0);
+            let mut store = shared.store.lock().unwrap();
+            store.tick();
+            match store.jobs.get_mut(&id) {
+                Some(job) if !matches!(job.state, State::Reserved(..)) => {
+                    job.reserves += 1;
+                    job.state =
+                        State::Reserved(session.id, Instant::now() + Duration::from_secs(job.ttr));
+                    with_body(format!("RESERVED {}", id), &job.body)
+                }
+                _ => b"NOT_FOUND\r\n".to_vec(),
+            }
+        }
+        "delete" => {
+            let id = arg::<u64>(parts, 1).unwrap_or(0);
+            let mut store = shared.store.lock().unwrap();
+            let deletable = match store.jobs.get(&id).map(|job| job.state) {
+                Some(State::Reserved(owner, _)) => owner == session.id,
+                Some(_) => true,
+                None => false,
+            };
+            if deletable && !shared.reject_deletes.load(Ordering::SeqCst) {
+                store.jobs.remove(&id);
+                b"DELETED\r\n".to_vec()
+            } else {
+                b"NOT_FOUND\r\n".to_vec()
+            }
+        }
+        "release" => {
+            let (id, priority, delay) = match (
+                arg::<u64>(parts, 1),
+                arg::<u32>(parts, 2),
+                arg::<u64>(parts, 3),
+            ) {
+                (Some(i), Some(p), Some(d)) => (i, p, d),
+                _ => return b"BAD_FORMAT\r\n".to_vec(),
+            };
+            let mut store = shared.store.lock().unwrap();
+            if !store.reserved_by(session.id, id) {
+                return b"NOT_FOUND\r\n".to_vec();
+            }
+            let job = store.jobs.get_mut(&id).unwrap();
+            job.priority = priority;
+            job.delay = delay;
+            job.releases += 1;
+            job.state = if delay > 0 {
+                State::Delayed(Instant::now() + Duration::from_secs(delay))
+            } else {
+                State::Ready
+            };
+            shared.changed.notify_all();
+            b"RELEASED\r\n".to_vec()
+        }
+        "bury" => {
+            let (id, priority) = match (arg::<u64>(parts, 1), arg::<u32>(parts, 2)) {
+                (Some(i), Some(p)) => (i, p),
+                _ => return b"BAD_FORMAT\r\n".to_vec(),
+            };
+            let mut store = shared.store.lock().unwrap();
+            if !store.reserved_by(session.id, id) {
+                return b"NOT_FOUND\r\n".to_vec();
+            }
+            let job = store.jobs.get_mut(&id).unwrap();
+            job.priority = priority;
+            job.buries += 1;
+            job.state = State::Buried;
+            b"BURIED\r\n".to_vec()
+        }
+        "touch" => {
+            let id = arg::<u64>(parts, 1).unwrap_or(0);
+            let mut store = shared.store.lock().unwrap();
+            if !store.reserved_by(session.id, id) || shared.reject_touches.load(Ordering::SeqCst) {
+                return b"NOT_FOUND\r\n".to_vec();
+            }
+            let job = store.jobs.get_mut(&id).unwrap();
+            job.state = State::Reserved(session.id, Instant::now() + Duration::from_secs(job.ttr));
+            b"TOUCHED\r\n".to_vec()
+        }
+        "kick-job" => {
+            let id = arg::<u64>(parts, 1).unwrap_or(0);
+            let mut store = shared.store.lock().unwrap();
+            match store.jobs.get_mut(&id) {
+                Some(job) if matches!(job.state, State::Buried | State::Delayed(_)) => {
+                    job.kicks += 1;
+                    job.state = State::Ready;
+                    shared.changed.notify_all();
+                    b"KICKED\r\n".to_vec()
+                }
+                _ => b"NOT_FOUND\r\n".to_vec(),
+            }
+        }
+        "kick" => {
+            let bound = arg::<usize>(parts, 1).unwrap_or(0);
+            let mut store = shared.store.lock().unwrap();
+            store.tick();
+            // Buried jobs are kicked first, delayed ones only when none is buried.
+            let in_tube = |job: &MockJob| job.tube == session.using;
+            let buried = store
+                .jobs
+                .values()
+                .any(|job| in_tube(job) && job.state == State::Buried);
+            let mut kicked = 0;
+            for job in store.jobs.values_mut().filter(|job| in_tube(job)) {
+                let kickable = match job.state {
+                    State::Buried => true,
+                    State::Delayed(_) => !buried,
+                    _ => false,
+                };
+                if kickable && kicked < bound {
+                    job.kicks += 1;
+                    job.state = State::Ready;
+                    kicked += 1;
+                }
+            }
+            shared.changed.notify_all();
+            format!("KICKED {}\r\n", kicked).into_bytes()
+        }
+        "peek-ready" | "peek-buried" => {
+            let mut store = shared.store.lock().unwrap();
+            store.tick();
+            let found = if parts[0] == "peek-ready" {
+                store.next_ready(std::slice::from_ref(&session.using))
+            } else {
+                store
+                    .jobs
+                    .iter()
+                    .find(|(_, job)| job.tube == session.using && job.state == State::Buried)
+                    .map(|(id, _)| *id)
+            };
+            match found {
+                Some(id) => with_body(format!("FOUND {}", id), &store.jobs[&id].body),
+                None => b"NOT_FOUND\r\n".to_vec(),
+            }
+        }
+        "peek" => {
+            let id = arg::<u64>(parts, 1).unwrap_or(0);
+            let store = shared.store.lock().unwrap();
+            match store.jobs.get(&id) {
+                Some(job) => with_body(format!("FOUND {}", id), &job.body),
+                None => b"NOT_FOUND\r\n".to_vec(),
+            }
+        }
+        "stats-job" => {
+            let id = arg::<u64>(parts, 1).unwrap_or(0);
+            let mut store = shared.store.lock().unwrap();
+            store.tick();
+            match store.jobs.get(&id) {
+                Some(job) => with_body("OK".to_string(), job_stats(id, job).as_bytes()),
+                None => b"NOT_FOUND\r\n".to_vec(),
+            }
+        }
+        "stats-tube" => {
+            let tube = parts.get(1).cloned().unwrap_or_default();
+            let mut store = shared.store.lock().unwrap();
+            store.tick();
+            if tube != DEFAULT_TUBE && !store.jobs.values().any(|job| job.tube == tube) {
+                return b"NOT_FOUND\r\n".to_vec();
+            }
+            let counts = store.counts(Some(&tube));
+            let yaml = format!(
+                "---\nname: {}\n{}total-jobs: {}\ncurrent-using: 1\n\
+                 current-waiting: 0\ncurrent-watching: 1\npause: 0\ncmd-delete: 0\n\
+                 cmd-pause-tube: 0\npause-time-left: 0\n",
+                tube, counts, store.next_id,
+            );
+            with_body("OK".to_string(), yaml.as_bytes())
+        }
+        "stats" => {
+            let mut store = shared.store.lock().unwrap();
+            store.tick();
+            let mut yaml = format!("---\n{}", store.counts(None));
+            for key in &[
+                "cmd-put",
+                "cmd-peek",
+                "cmd-peek-ready",
+                "cmd-peek-delayed",
+                "cmd-peek-buried",
+                "cmd-reserve",
+                "cmd-use",
+                "cmd-watch",
+                "cmd-ignore",
+                "cmd-delete",
+                "cmd-release",
+                "cmd-bury",
+                "cmd-kick",
+                "cmd-stats",
+                "cmd-stats-job",
+                "cmd-stats-tube",
+                "cmd-list-tubes",
+                "cmd-list-tube-used",
+                "cmd-list-tubes-watched",
+                "cmd-pause-tube",
+                "job-timeouts",
+                "current-tubes",
+                "current-connections",
+                "current-producers",
+                "current-workers",
+                "current-waiting",
+                "total-connections",
+                "binlog-oldest-index",
+                "binlog-current-index",
+                "binlog-records-written",
+                "binlog-records-migrated",
+            ] {
+                yaml.push_str(&format!("{}: 0\n", key));
+            }
+            yaml.push_str(&format!(
+                "total-jobs: {}\nmax-job-size: 65535\npid: 1\nversion: \"mock\"\n\
+                 rusage-utime: 0.000000\nrusage-stime: 0.000000\nuptime: 0\n\
+                 binlog-max-size: 10485760\nid: mock\nhostname: localhost\n",
+                store.next_id,
+            ));
+            with_body("OK".to_string(), yaml.as_bytes())
+        }
+        _ => b"UNKNOWN_COMMAND\r\n".to_vec(),
+    }
+}
+
+fn reserve(shared: &Shared, session: &Session, timeout: Option<Duration>) -> Vec<u8> {
+    let deadline = timeout.map(|t| Instant::now() + t);
+    let mut store = shared.store.lock().unwrap();
+    loop {
+        store.tick();
+        if store.deadline_soon(session.id) {
+            return b"DEADLINE_SOON\r\n".to_vec();
+        }
+        if let Some(id) = store.next_ready(&session.watching) {
+            let job = store.jobs.get_mut(&id).unwrap();
+            job.reserves += 1;
+            job.state = State::Reserved(session.id, Instant::now() + Duration::from_secs(job.ttr));
+            return with_body(format!("RESERVED {}", id), &job.body);
+        }
+
+        // Wake up at least every 100ms so delayed jobs and TTRs are noticed.
+        let mut wait = Duration::from_millis(100);
+        if let Some(deadline) = deadline {
+            let now = Instant::now();
+            if deadline <= now {
+                return b"TIMED_OUT\r\n".to_vec();
+            }
+            wait = wait.min(deadline - now);
+        }
+        store = shared.changed.wait_timeout(store, wait).unwrap().0;
+    }
+}
+
+fn job_stats(id: u64, job: &MockJob) -> String {
+    let now = Instant::now();
+    let (state, time_left) = match job.state {
+        State::Ready => ("ready", 0),
+        State::Delayed(at) => ("delayed", at.saturating_duration_since(now).as_secs()),
+        State::Reserved(_, deadline) => (
+            "reserved",
+            deadline.saturating_duration_since(now).as_secs(),
+        ),
+        State::Buried => ("buried", 0),
+    };
+    format!(
+        "---\nid: {}\ntube: {}\nstate: {}\npri: {}\nage: {}\ndelay: {}\nttr: {}\n\
+         time-left: {}\nfile: 0\nreserves: {}\ntimeouts: {}\nreleases: {}\n\
+         buries: {}\nkicks: {}\n",
+        id,
+        job.tube,
+        state,
+        job.priority,
+        now.duration_since(job.created).as_secs(),
+        job.delay,
+        job.ttr,
+        time_left,
+        job.reserves,
+        job.timeouts,
+        job.releases,
+        job.buries,
+        job.kicks,
+    )
+}
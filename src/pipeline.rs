@@ -0,0 +1,361 @@
+use std::fmt;
+use std::time::Duration;
+
+use crate::command::{self, Command};
+use crate::config::*;
+use crate::error::BeanstalkcResult;
+use crate::response::Response;
+use crate::Beanstalkc;
+
+// Number of commands written per flush by `Pipeline::execute`, so that the replies
+// of a chunk always fit in the socket buffers.
+const PIPELINE_CHUNK_SIZE: usize = 256;
+
+/// `Reply` is the successful answer to a pipelined command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reply {
+    /// The id of the job created by `put`.
+    Inserted(u64),
+    /// The tube now used, answered by `use`.
+    Using(String),
+    /// The number of tubes now watched, answered by `watch` and `ignore`.
+    Watching(u64),
+    /// The number of jobs kicked by `kick`.
+    Kicked(u64),
+    /// The command succeeded without returning anything, e.g. `delete`.
+    Done,
+}
+
+/// Session state change to record once the command succeeded.
+#[derive(Debug)]
+enum SessionChange {
+    Use,
+    Watch(String),
+    Ignore(String),
+}
+
+struct Entry<'a> {
+    cmd: Command<'a>,
+    reply: fn(&Response) -> BeanstalkcResult<Reply>,
+    change: Option<SessionChange>,
+}
+
+/// `Pipeline` queues commands to send them in one write and flush, then reads the
+/// responses in order, saving a round trip per command.
+///
+/// Since the server only reads the next command after writing the previous
+/// response, `execute` writes long pipelines in chunks, so any number of commands
+/// may be queued.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use beanstalkc::{Beanstalkc, Reply};
+///
+/// let mut conn = Beanstalkc::new().connect().unwrap();
+///
+/// let replies = conn
+///     .pipeline()
+///     .use_tube("jobs")
+///     .put(b"first", 0, Duration::from_secs(0), Duration::from_secs(60))
+///     .put(b"second", 0, Duration::from_secs(0), Duration::from_secs(60))
+///     .execute();
+/// for reply in replies {
+///     if let Ok(Reply::Inserted(id)) = reply {
+///         dbg!(id);
+///     }
+/// }
+/// ```
+pub struct Pipeline<'a> {
+    conn: &'a mut Beanstalkc,
+    entries: Vec<Entry<'a>>,
+}
+
+impl<'a> Pipeline<'a> {
+    pub(crate) fn new(conn: &'a mut Beanstalkc) -> Pipeline<'a> {
+        Pipeline {
+            conn,
+            entries: vec![],
+        }
+    }
+
+    fn push(
+        mut self,
+        cmd: Command<'a>,
+        reply: fn(&Response) -> BeanstalkcResult<Reply>,
+        change: Option<SessionChange>,
+    ) -> Self {
+        self.entries.push(Entry { cmd, reply, change });
+        self
+    }
+
+    /// Queue a put into the current tube with default configs.
+    pub fn put_default(self, body: &'a [u8]) -> Self {
+        self.put(
+            body,
+            DEFAULT_JOB_PRIORITY,
+            DEFAULT_JOB_DELAY,
+            DEFAULT_JOB_TTR,
+        )
+    }
+
+    /// Queue a put into the current tube.
+    pub fn put(self, body: &'a [u8], priority: u32, delay: Duration, ttr: Duration) -> Self {
+        self.push(command::put(body, priority, delay, ttr), inserted, None)
+    }
+
+    /// Queue a use of the given tube.
+    pub fn use_tube(self, name: &str) -> Self {
+        self.push(command::use_tube(name), using, Some(SessionChange::Use))
+    }
+
+    /// Queue a watch of the given tube.
+    pub fn watch(self, name: &str) -> Self {
+        let change = SessionChange::Watch(name.to_string());
+        self.push(command::watch(name), watching, Some(change))
+    }
+
+    /// Queue an ignore of the given tube.
+    pub fn ignore(self, name: &str) -> Self {
+        let change = SessionChange::Ignore(name.to_string());
+        self.push(command::ignore(name), watching, Some(change))
+    }
+
+    /// Queue a delete of the given job.
+    pub fn delete(self, job_id: u64) -> Self {
+        self.push(command::delete(job_id), done, None)
+    }
+
+    /// Queue a release of the given job.
+    pub fn release(self, job_id: u64, priority: u32, delay: Duration) -> Self {
+        self.push(command::release(job_id, priority, delay), done, None)
+    }
+
+    /// Queue a bury of the given job.
+    pub fn bury(self, job_id: u64, priority: u32) -> Self {
+        self.push(command::bury(job_id, priority), done, None)
+    }
+
+    /// Queue a touch of the given job.
+    pub fn touch(self, job_id: u64) -> Self {
+        self.push(command::touch(job_id), done, None)
+    }
+
+    /// Queue a kick of at most `bound` jobs in the current tube.
+    pub fn kick(self, bound: u32) -> Self {
+        self.push(command::kick(bound), kicked, None)
+    }
+
+    /// Queue a kick of the given job.
+    pub fn kick_job(self, job_id: u64) -> Self {
+        self.push(command::kick_job(job_id), done, None)
+    }
+
+    /// Return the number of queued commands.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Return whether no command is queued.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Send the queued commands and return one result per command, in order.
+    ///
+    /// Commands are written in chunks, each flushed before reading its replies, so
+    /// that a long pipeline cannot fill the socket buffers in both directions.
+    ///
+    /// When the connection itself fails, it is closed and every command left
+    /// unanswered reports that error, although some of them may have been executed.
+    /// The replies of the chunks answered before are kept.
+    pub fn execute(self) -> Vec<BeanstalkcResult<Reply>> {
+        let conn = self.conn;
+        let (cmds, entries): (Vec<_>, Vec<_>) = self
+            .entries
+            .into_iter()
+            .map(|entry| (entry.cmd, (entry.reply, entry.change)))
+            .unzip();
+
+        let mut replies = Vec::with_capacity(cmds.len());
+        let mut entries = entries.into_iter();
+        for chunk in cmds.chunks(PIPELINE_CHUNK_SIZE) {
+            let responses = match conn.send_many(chunk) {
+                Ok(responses) => responses,
+                Err(e) => {
+                    replies.resize(cmds.len(), Err(e));
+                    break;
+                }
+            };
+            for ((cmd, resp), (reply, change)) in chunk.iter().zip(responses).zip(&mut entries) {
+                let result = cmd.check(resp).and_then(|resp| reply(&resp));
+                if let (Ok(reply), Some(change)) = (&result, change) {
+                    match (reply, change) {
+                        (Reply::Using(tube), SessionChange::Use) => conn.record_use(tube),
+                        (_, SessionChange::Watch(tube)) => conn.record_watch(&tube),
+                        (_, SessionChange::Ignore(tube)) => conn.record_ignore(&tube),
+                        _ => {}
+                    }
+                }
+                replies.push(result);
+            }
+        }
+        replies
+    }
+}
+
+impl<'a> fmt::Debug for Pipeline<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cmds: Vec<_> = self.entries.iter().map(|entry| &entry.cmd).collect();
+        f.debug_struct("Pipeline").field("commands", &cmds).finish()
+    }
+}
+
+fn inserted(resp: &Response) -> BeanstalkcResult<Reply> {
+    resp.job_id().map(Reply::Inserted)
+}
+
+fn using(resp: &Response) -> BeanstalkcResult<Reply> {
+    resp.get_param(0).map(Reply::Using)
+}
+
+fn watching(resp: &Response) -> BeanstalkcResult<Reply> {
+    resp.get_int_param(0).map(Reply::Watching)
+}
+
+fn kicked(resp: &Response) -> BeanstalkcResult<Reply> {
+    resp.get_int_param(0).map(Reply::Kicked)
+}
+
+fn done(_: &Response) -> BeanstalkcResult<Reply> {
+    Ok(Reply::Done)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::BeanstalkcError;
+    use crate::mock::MockServer;
+    use crate::stats::JobState;
+
+    #[test]
+    fn test_execute_many_commands() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        // The replies alone are far larger than the socket buffers.
+        let tube = "t".repeat(200);
+        let pipeline = (0..100_000).fold(conn.pipeline(), |pipeline, _| pipeline.use_tube(&tube));
+        let replies = pipeline.execute();
+        assert_eq!(100_000, replies.len());
+        assert!(replies
+            .iter()
+            .all(|reply| *reply.as_ref().unwrap() == Reply::Using(tube.clone())));
+        assert_eq!(tube, conn.using().unwrap());
+    }
+
+    #[test]
+    fn test_execute_in_order() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        let replies = conn
+            .pipeline()
+            .put_default(b"first")
+            .put_default(b"second")
+            .put(
+                b"third",
+                7,
+                Duration::from_secs(10),
+                Duration::from_secs(60),
+            )
+            .delete(1)
+            .delete(42)
+            .execute();
+        assert_eq!(5, replies.len());
+        assert_eq!(Reply::Inserted(1), *replies[0].as_ref().unwrap());
+        assert_eq!(Reply::Inserted(2), *replies[1].as_ref().unwrap());
+        assert_eq!(Reply::Inserted(3), *replies[2].as_ref().unwrap());
+        assert_eq!(Reply::Done, *replies[3].as_ref().unwrap());
+        assert!(matches!(replies[4], Err(BeanstalkcError::NotFound)));
+
+        assert!(conn.peek(1).is_err());
+        let stats = conn.stats_job(3).unwrap();
+        assert_eq!(JobState::Delayed, stats.state);
+        assert_eq!(7, stats.pri);
+    }
+
+    #[test]
+    fn test_records_session_state() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        let replies = conn
+            .pipeline()
+            .use_tube("jobs")
+            .watch("jobs")
+            .ignore("default")
+            .put_default(b"job")
+            .execute();
+        assert_eq!(
+            Reply::Using("jobs".to_string()),
+            *replies[0].as_ref().unwrap()
+        );
+        assert_eq!(Reply::Watching(1), *replies[2].as_ref().unwrap());
+        assert_eq!("jobs", conn.stats_job(1).unwrap().tube);
+
+        let mut conn = conn.reconnect().unwrap();
+        assert_eq!("jobs", conn.using().unwrap());
+        assert_eq!(vec!["jobs".to_string()], conn.watching().unwrap());
+    }
+
+    #[test]
+    fn test_execute_empty() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        let pipeline = conn.pipeline();
+        assert!(pipeline.is_empty());
+        assert!(pipeline.execute().is_empty());
+    }
+
+    #[test]
+    fn test_connection_failure() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+        conn.using().unwrap();
+
+        server.drop_connections();
+        let replies = conn.pipeline().put_default(b"job").execute();
+        assert_eq!(1, replies.len());
+        assert!(replies[0].is_err());
+        assert!(matches!(
+            conn.using(),
+            Err(BeanstalkcError::ConnectionError(_))
+        ));
+    }
+
+    #[test]
+    fn test_connection_failure_keeps_answered_chunks() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+
+        let count = PIPELINE_CHUNK_SIZE + 10;
+        let pipeline = (0..count).fold(conn.pipeline().use_tube("jobs"), |pipeline, _| {
+            pipeline.put_default(b"job")
+        });
+        server.hang_up_after(PIPELINE_CHUNK_SIZE);
+        let replies = pipeline.execute();
+        assert_eq!(count + 1, replies.len());
+        assert_eq!(
+            Reply::Using("jobs".to_string()),
+            *replies[0].as_ref().unwrap()
+        );
+        assert!(replies[1..PIPELINE_CHUNK_SIZE].iter().all(Result::is_ok));
+        assert!(replies[PIPELINE_CHUNK_SIZE..].iter().all(Result::is_err));
+
+        let mut conn = conn.reconnect().unwrap();
+        assert_eq!("jobs", conn.using().unwrap());
+    }
+}
@@ -0,0 +1,467 @@
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::error::{BeanstalkcError, BeanstalkcResult};
+use crate::Beanstalkc;
+
+type Factory = dyn Fn() -> BeanstalkcResult<Beanstalkc> + Send + Sync;
+
+/// `PoolBuilder` configures a `Pool`.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use beanstalkc::{Beanstalkc, Pool};
+///
+/// let pool = Pool::builder()
+///     .max_size(4)
+///     .idle_timeout(Some(Duration::from_secs(60)))
+///     .build(|| Beanstalkc::new().initial_tube("jobs").connect());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolBuilder {
+    max_size: usize,
+    idle_timeout: Option<Duration>,
+    checkout_timeout: Option<Duration>,
+}
+
+impl Default for PoolBuilder {
+    fn default() -> Self {
+        PoolBuilder {
+            max_size: 10,
+            idle_timeout: Some(Duration::from_secs(600)),
+            checkout_timeout: Some(Duration::from_secs(30)),
+        }
+    }
+}
+
+impl PoolBuilder {
+    /// Set the maximum number of connections open at once. Default is `10`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is `0`.
+    pub fn max_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "pool max size must be positive");
+        self.max_size = size;
+        self
+    }
+
+    /// Set how long a connection may stay idle in the pool before being closed.
+    /// `None` keeps idle connections forever. Default is `600s`.
+    pub fn idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Set how long `Pool::get` waits for a connection when `max_size` connections
+    /// are in use. `None` waits forever. Default is `30s`.
+    pub fn checkout_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.checkout_timeout = timeout;
+        self
+    }
+
+    /// Build the pool, opening connections lazily with `factory`.
+    ///
+    /// The used tube and the watch list of a connection when `factory` returns it
+    /// are the defaults it is reset to whenever it goes back to the pool.
+    pub fn build<F>(self, factory: F) -> Pool
+    where
+        F: Fn() -> BeanstalkcResult<Beanstalkc> + Send + Sync + 'static,
+    {
+        Pool {
+            shared: Arc::new(Shared {
+                config: self,
+                factory: Box::new(factory),
+                state: Mutex::new(State {
+                    idle: vec![],
+                    open: 0,
+                }),
+                released: Condvar::new(),
+            }),
+        }
+    }
+}
+
+/// A connection with the session state it is reset to.
+#[derive(Debug)]
+struct Entry {
+    conn: Beanstalkc,
+    tube: String,
+    watching: Vec<String>,
+}
+
+impl Entry {
+    /// Use and watch the default tubes again in a single round trip.
+    fn reset(&mut self) -> BeanstalkcResult<()> {
+        let Entry {
+            conn,
+            tube,
+            watching: defaults,
+        } = self;
+        let watching = conn.watched_tubes();
+        let use_tube = conn.current_tube() != tube;
+        let mut pipeline = conn.pipeline();
+        if use_tube {
+            pipeline = pipeline.use_tube(tube);
+        }
+        for tube in defaults.iter().filter(|x| !watching.contains(x)) {
+            pipeline = pipeline.watch(tube);
+        }
+        for tube in watching.iter().filter(|x| !defaults.contains(x)) {
+            pipeline = pipeline.ignore(tube);
+        }
+        pipeline
+            .execute()
+            .into_iter()
+            .try_for_each(|x| x.map(|_| ()))
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    idle: Vec<(Entry, Instant)>,
+    // Connections handed out or idle.
+    open: usize,
+}
+
+struct Shared {
+    config: PoolBuilder,
+    factory: Box<Factory>,
+    state: Mutex<State>,
+    released: Condvar,
+}
+
+impl Shared {
+    /// Give a connection back to the pool, or forget it when it can't be reused.
+    fn put_back(&self, entry: Option<Entry>) {
+        let mut state = self.state.lock().unwrap();
+        match entry {
+            Some(entry) => state.idle.push((entry, Instant::now())),
+            None => state.open -= 1,
+        }
+        self.released.notify_one();
+    }
+}
+
+/// `Pool` shares beanstalkd connections between threads.
+///
+/// Connections are opened on demand, up to a maximum size. A connection taken out
+/// of the pool is checked to be alive first, and gets the used tube and watch list
+/// it had when opened back before being reused.
+///
+/// Pools are cheap to clone: clones share the same connections.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::thread;
+/// use beanstalkc::{Beanstalkc, Pool};
+///
+/// let pool = Pool::new(|| Beanstalkc::new().connect());
+///
+/// let handles: Vec<_> = (0..4)
+///     .map(|_| {
+///         let pool = pool.clone();
+///         thread::spawn(move || {
+///             let mut conn = pool.get().unwrap();
+///             conn.use_tube("jobs").unwrap();
+///             conn.put_default(b"hello").unwrap();
+///         })
+///     })
+///     .collect();
+/// for handle in handles {
+///     handle.join().unwrap();
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Pool {
+    shared: Arc<Shared>,
+}
+
+impl Pool {
+    /// Create a pool with the default settings, opening connections with `factory`.
+    pub fn new<F>(factory: F) -> Pool
+    where
+        F: Fn() -> BeanstalkcResult<Beanstalkc> + Send + Sync + 'static,
+    {
+        Pool::builder().build(factory)
+    }
+
+    /// Create a `PoolBuilder` to configure a pool.
+    pub fn builder() -> PoolBuilder {
+        PoolBuilder::default()
+    }
+
+    /// Take a connection out of the pool, opening a new one if none is idle and the
+    /// pool isn't full, or waiting for one to be returned otherwise.
+    pub fn get(&self) -> BeanstalkcResult<PooledConnection> {
+        let deadline = self
+            .shared
+            .config
+            .checkout_timeout
+            .map(|x| Instant::now() + x);
+        loop {
+            let (idle, expired) = self.checkout(deadline)?;
+            drop(expired);
+
+            let mut entry = match idle {
+                Some(entry) => entry,
+                None => return self.open(),
+            };
+            if entry.conn.using().is_ok() {
+                return Ok(PooledConnection {
+                    shared: self.shared.clone(),
+                    entry: Some(entry),
+                });
+            }
+            // The connection is broken: forget it and try again.
+            self.shared.put_back(None);
+        }
+    }
+
+    /// Wait until a connection is idle or can be opened, reserving the slot in the
+    /// latter case. Also return the connections idle for too long, to be closed
+    /// once the lock is released.
+    fn checkout(&self, deadline: Option<Instant>) -> BeanstalkcResult<(Option<Entry>, Vec<Entry>)> {
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            let mut expired = vec![];
+            if let Some(timeout) = self.shared.config.idle_timeout {
+                let now = Instant::now();
+                let (keep, old) = state
+                    .idle
+                    .drain(..)
+                    .partition(|(_, since)| now.duration_since(*since) < timeout);
+                state.idle = keep;
+                state.open -= old.len();
+                expired = old.into_iter().map(|(entry, _)| entry).collect();
+            }
+
+            if let Some((entry, _)) = state.idle.pop() {
+                return Ok((Some(entry), expired));
+            }
+            if state.open < self.shared.config.max_size {
+                state.open += 1;
+                return Ok((None, expired));
+            }
+
+            state = match deadline {
+                None => self.shared.released.wait(state).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(BeanstalkcError::ConnectionError(
+                            "timed out waiting for a pooled connection".to_string(),
+                        ));
+                    }
+                    self.shared
+                        .released
+                        .wait_timeout(state, deadline - now)
+                        .unwrap()
+                        .0
+                }
+            };
+        }
+    }
+
+    /// Open a connection in the slot reserved by `checkout`.
+    fn open(&self) -> BeanstalkcResult<PooledConnection> {
+        match (self.shared.factory)() {
+            Ok(conn) => {
+                let tube = conn.current_tube().to_string();
+                let watching = conn.watched_tubes();
+                Ok(PooledConnection {
+                    shared: self.shared.clone(),
+                    entry: Some(Entry {
+                        conn,
+                        tube,
+                        watching,
+                    }),
+                })
+            }
+            Err(e) => {
+                self.shared.put_back(None);
+                Err(e)
+            }
+        }
+    }
+
+    /// Return the number of connections open, whether in use or idle.
+    pub fn open_connections(&self) -> usize {
+        self.shared.state.lock().unwrap().open
+    }
+
+    /// Return the number of idle connections.
+    pub fn idle_connections(&self) -> usize {
+        self.shared.state.lock().unwrap().idle.len()
+    }
+}
+
+impl fmt::Debug for Pool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.shared.state.lock().unwrap();
+        f.debug_struct("Pool")
+            .field("config", &self.shared.config)
+            .field("open", &state.open)
+            .field("idle", &state.idle.len())
+            .finish()
+    }
+}
+
+/// `PooledConnection` is a connection taken out of a `Pool`, which it goes back to
+/// when dropped.
+#[derive(Debug)]
+pub struct PooledConnection {
+    shared: Arc<Shared>,
+    entry: Option<Entry>,
+}
+
+impl Deref for PooledConnection {
+    type Target = Beanstalkc;
+
+    fn deref(&self) -> &Beanstalkc {
+        &self.entry.as_ref().unwrap().conn
+    }
+}
+
+impl DerefMut for PooledConnection {
+    fn deref_mut(&mut self) -> &mut Beanstalkc {
+        &mut self.entry.as_mut().unwrap().conn
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        let entry = self.entry.take().and_then(|mut entry| match entry.reset() {
+            Ok(()) => Some(entry),
+            Err(_) => None,
+        });
+        self.shared.put_back(entry);
+    }
+}
+
+impl fmt::Debug for Shared {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Shared")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    use super::*;
+    use crate::mock::MockServer;
+
+    fn pool(server: &MockServer, builder: PoolBuilder) -> (Pool, Arc<AtomicUsize>) {
+        let port = server.port();
+        let opened = Arc::new(AtomicUsize::new(0));
+        let counter = opened.clone();
+        let pool = builder.build(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Beanstalkc::new().port(port).connect()
+        });
+        (pool, opened)
+    }
+
+    #[test]
+    fn test_reuse_connection() {
+        let server = MockServer::start();
+        let (pool, opened) = pool(&server, Pool::builder());
+
+        pool.get().unwrap().put_default(b"job").unwrap();
+        pool.get().unwrap().put_default(b"job").unwrap();
+        assert_eq!(1, opened.load(Ordering::SeqCst));
+        assert_eq!(1, pool.idle_connections());
+    }
+
+    #[test]
+    fn test_reset_on_return() {
+        let server = MockServer::start();
+        let port = server.port();
+        let pool = Pool::new(move || {
+            Beanstalkc::new()
+                .port(port)
+                .initial_tube("jobs")
+                .initial_watch_list(&["jobs"])
+                .connect()
+        });
+
+        {
+            let mut conn = pool.get().unwrap();
+            conn.use_tube("other").unwrap();
+            conn.watch("other").unwrap();
+            conn.watch("default").unwrap();
+            conn.ignore("jobs").unwrap();
+        }
+
+        let mut conn = pool.get().unwrap();
+        assert_eq!("jobs", conn.using().unwrap());
+        assert_eq!(vec!["jobs".to_string()], conn.watching().unwrap());
+        assert_eq!(1, pool.open_connections());
+    }
+
+    #[test]
+    fn test_max_size() {
+        let server = MockServer::start();
+        let builder = Pool::builder()
+            .max_size(2)
+            .checkout_timeout(Some(Duration::from_millis(50)));
+        let (pool, opened) = pool(&server, builder);
+
+        let first = pool.get().unwrap();
+        let _second = pool.get().unwrap();
+        assert!(matches!(
+            pool.get(),
+            Err(BeanstalkcError::ConnectionError(_))
+        ));
+
+        let waiting = {
+            let pool = pool.clone();
+            thread::spawn(move || pool.get().map(|_| ()))
+        };
+        thread::sleep(Duration::from_millis(10));
+        drop(first);
+        waiting.join().unwrap().unwrap();
+        assert_eq!(2, opened.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_idle_timeout() {
+        let server = MockServer::start();
+        let builder = Pool::builder().idle_timeout(Some(Duration::from_millis(20)));
+        let (pool, opened) = pool(&server, builder);
+
+        drop(pool.get().unwrap());
+        thread::sleep(Duration::from_millis(50));
+        drop(pool.get().unwrap());
+        assert_eq!(2, opened.load(Ordering::SeqCst));
+        assert_eq!(1, pool.open_connections());
+    }
+
+    #[test]
+    fn test_health_check() {
+        let server = MockServer::start();
+        let (pool, opened) = pool(&server, Pool::builder());
+
+        pool.get().unwrap().using().unwrap();
+        server.drop_connections();
+        pool.get().unwrap().put_default(b"job").unwrap();
+        assert_eq!(2, opened.load(Ordering::SeqCst));
+        assert_eq!(1, pool.open_connections());
+    }
+
+    #[test]
+    fn test_factory_error() {
+        let pool = Pool::new(|| Beanstalkc::new().port(1).connect());
+        assert!(pool.get().is_err());
+        assert_eq!(0, pool.open_connections());
+    }
+}
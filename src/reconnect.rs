@@ -0,0 +1,104 @@
+use std::time::Duration;
+
+/// `ReconnectPolicy` tells a `Beanstalkc` connection how to reconnect when it finds
+/// the connection broken.
+///
+/// Attempts are spaced by an exponential backoff: the first one is immediate, the
+/// second waits `initial_delay`, and every following one waits twice as long as the
+/// previous, up to `max_delay`.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use beanstalkc::{Beanstalkc, ReconnectPolicy};
+///
+/// let mut conn = Beanstalkc::new()
+///        .auto_reconnect(ReconnectPolicy::new().max_attempts(5))
+///        .connect()
+///        .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    max_attempts: u32,
+    initial_delay: Duration,
+    max_delay: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Create a new `ReconnectPolicy` making 3 attempts, waiting 100ms then 200ms
+    /// between them.
+    pub fn new() -> ReconnectPolicy {
+        ReconnectPolicy::default()
+    }
+
+    /// Set how many times to try reconnecting before giving up.
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts;
+        self
+    }
+
+    /// Set the delay before the second attempt.
+    pub fn initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
+        self
+    }
+
+    /// Set the upper bound of the delay between two attempts.
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    pub(crate) fn attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Return the delay before the given attempt, counting from 0.
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        if attempt == 0 {
+            return Duration::from_secs(0);
+        }
+
+        let factor = 1u32.checked_shl(attempt - 1).unwrap_or(u32::MAX);
+        self.initial_delay
+            .checked_mul(factor)
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay() {
+        let policy = ReconnectPolicy::new()
+            .initial_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_secs(1));
+
+        let delays: Vec<_> = (0..6).map(|attempt| policy.delay(attempt)).collect();
+        assert_eq!(
+            vec![
+                Duration::from_millis(0),
+                Duration::from_millis(100),
+                Duration::from_millis(200),
+                Duration::from_millis(400),
+                Duration::from_millis(800),
+                Duration::from_secs(1),
+            ],
+            delays
+        );
+        assert_eq!(Duration::from_secs(1), policy.delay(100));
+    }
+}
@@ -1,50 +1,51 @@
-use std::io::{BufRead, Read, Write};
-use std::net::TcpStream;
-use std::str::FromStr;
+use std::io::{self, BufRead, Read, Write};
 
 use bufstream::BufStream;
 
-use crate::command::Status;
-use crate::error::{BeanstalkcError, BeanstalkcResult};
+use crate::error::BeanstalkcResult;
 use crate::response::Response;
+use crate::transport::Transport;
 
 #[derive(Debug)]
 pub struct Request<'b> {
-    stream: &'b mut BufStream<TcpStream>,
+    stream: &'b mut BufStream<Box<dyn Transport>>,
 }
 
 impl<'b> Request<'b> {
-    pub fn new(stream: &'b mut BufStream<TcpStream>) -> Self {
+    pub fn new(stream: &'b mut BufStream<Box<dyn Transport>>) -> Self {
         Request { stream }
     }
 
     pub fn send(&mut self, message: &[u8]) -> BeanstalkcResult<Response> {
-        let _ = self.stream.write(message)?;
+        self.stream.write_all(message)?;
         self.stream.flush()?;
+        self.receive()
+    }
 
-        let mut line = String::new();
-        self.stream.read_line(&mut line)?;
-
-        if line.trim().is_empty() {
-            return Err(BeanstalkcError::UnexpectedResponse(
-                "empty response".to_string(),
-            ));
+    /// Write all the messages with a single flush, then read their responses in order.
+    pub fn send_many<M: AsRef<[u8]>>(&mut self, messages: &[M]) -> BeanstalkcResult<Vec<Response>> {
+        for message in messages {
+            self.stream.write_all(message.as_ref())?;
         }
+        self.stream.flush()?;
+        messages.iter().map(|_| self.receive()).collect()
+    }
 
-        let line_parts: Vec<_> = line.split_whitespace().collect();
-
-        let mut response = Response::default();
-        response.status = Status::from_str(line_parts.first().unwrap_or(&""))?;
-        response.params = line_parts[1..].iter().map(|&x| x.to_string()).collect();
+    fn receive(&mut self) -> BeanstalkcResult<Response> {
+        let mut line = String::new();
+        if self.stream.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed by server",
+            )
+            .into());
+        }
 
-        let body_byte_count = match response.status {
-            Status::Ok => response.get_int_param(0)?,
-            Status::Reserved => response.get_int_param(1)?,
-            Status::Found => response.get_int_param(1)?,
-            _ => {
-                return Ok(response);
-            }
-        } as usize;
+        let mut response = Response::parse_line(&line)?;
+        let body_byte_count = match response.body_len()? {
+            Some(len) => len,
+            None => return Ok(response),
+        };
 
         let mut tmp: Vec<u8> = vec![0; body_byte_count + 2]; // +2 trailing line break
         let body = &mut tmp[..];
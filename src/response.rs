@@ -1,7 +1,8 @@
+use std::str::FromStr;
+
 use crate::command::Status;
 use crate::error::{BeanstalkcError, BeanstalkcResult};
-use serde_yaml;
-use std::collections::HashMap;
+use serde::de::DeserializeOwned;
 
 #[derive(Debug)]
 pub struct Response {
@@ -11,6 +12,34 @@ pub struct Response {
 }
 
 impl Response {
+    /// Parse the status line of a response, without its body.
+    pub fn parse_line(line: &str) -> BeanstalkcResult<Response> {
+        if line.trim().is_empty() {
+            return Err(BeanstalkcError::UnexpectedResponse(
+                "empty response".to_string(),
+            ));
+        }
+
+        let line_parts: Vec<_> = line.split_whitespace().collect();
+
+        Ok(Response {
+            status: Status::from_str(line_parts[0])?,
+            params: line_parts[1..].iter().map(|&x| x.to_string()).collect(),
+            ..Response::default()
+        })
+    }
+
+    /// Return the number of body bytes following the status line, if any.
+    pub fn body_len(&self) -> BeanstalkcResult<Option<usize>> {
+        let len = match self.status {
+            Status::Ok => self.get_int_param(0)?,
+            Status::Reserved => self.get_int_param(1)?,
+            Status::Found => self.get_int_param(1)?,
+            _ => return Ok(None),
+        };
+        Ok(Some(len as usize))
+    }
+
     pub fn job_id(&self) -> BeanstalkcResult<u64> {
         self.get_int_param(0)
     }
@@ -30,26 +59,36 @@ impl Response {
         }
     }
 
-    pub fn body_as_map(&self) -> BeanstalkcResult<HashMap<String, String>> {
-        let res = match &self.body {
-            None => HashMap::default(),
-            Some(b) => {
-                let b = std::str::from_utf8(b)?;
-                serde_yaml::from_str(b).unwrap_or_default()
+    /// Convert an error status into the matching `BeanstalkcError`.
+    pub fn to_error(&self) -> BeanstalkcError {
+        match self.status {
+            Status::TimedOut => BeanstalkcError::TimedOut,
+            Status::DeadlineSoon => BeanstalkcError::DeadlineSoon,
+            Status::NotFound => BeanstalkcError::NotFound,
+            Status::Draining => BeanstalkcError::Draining,
+            Status::JobTooBig => BeanstalkcError::JobTooBig,
+            Status::Buried => match self.job_id() {
+                Ok(id) => BeanstalkcError::Buried(id),
+                Err(e) => e,
             },
-        };
-        Ok(res)
+            status => BeanstalkcError::CommandFailed(status),
+        }
     }
 
-    pub fn body_as_vec(&self) -> BeanstalkcResult<Vec<String>> {
-        let res = match &self.body {
-            None => Vec::default(),
+    pub fn body_as<T: DeserializeOwned>(&self) -> BeanstalkcResult<T> {
+        match &self.body {
+            None => Err(BeanstalkcError::UnexpectedResponse(
+                "missing response body".to_string(),
+            )),
             Some(b) => {
                 let b = std::str::from_utf8(b)?;
-                serde_yaml::from_str(b).unwrap_or_default()
+                Ok(serde_yaml::from_str(b)?)
             }
-        };
-        Ok(res)
+        }
+    }
+
+    pub fn body_as_vec(&self) -> BeanstalkcResult<Vec<String>> {
+        self.body_as()
     }
 }
 
@@ -66,6 +105,7 @@ impl Default for Response {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashMap;
 
     #[test]
     fn test_get_job_id() {
@@ -92,6 +132,26 @@ mod tests {
         assert_eq!(5, r.unwrap());
     }
 
+    #[test]
+    fn test_to_error() {
+        let resp = Response {
+            status: Status::Buried,
+            params: vec!["7".to_string()],
+            body: None,
+        };
+        assert!(matches!(resp.to_error(), BeanstalkcError::Buried(7)));
+
+        let resp = Response {
+            status: Status::NotIgnored,
+            params: vec![],
+            body: None,
+        };
+        assert!(matches!(
+            resp.to_error(),
+            BeanstalkcError::CommandFailed(Status::NotIgnored)
+        ));
+    }
+
     #[test]
     fn test_get_body_as_vec() {
         let resp = Response {
@@ -105,15 +165,33 @@ mod tests {
     }
 
     #[test]
-    fn test_get_body_as_map() {
+    fn test_get_body_as() {
         let resp = Response {
             status: Status::Ok,
             params: vec![],
             body: Some(b"name: default\nuptime: 12345".to_vec()),
         };
 
-        let stats = resp.body_as_map().unwrap();
+        let stats: HashMap<String, String> = resp.body_as().unwrap();
         assert_eq!(stats["name"], "default".to_string());
         assert_eq!(stats["uptime"], "12345".to_string());
     }
+
+    #[test]
+    fn test_get_body_as_invalid_yaml() {
+        let resp = Response {
+            status: Status::Ok,
+            params: vec![],
+            body: Some(b"- default\n  jobs: [".to_vec()),
+        };
+
+        assert!(resp.body_as::<HashMap<String, String>>().is_err());
+        assert!(resp.body_as_vec().is_err());
+    }
+
+    #[test]
+    fn test_get_body_as_missing_body() {
+        let resp = Response::default();
+        assert!(resp.body_as_vec().is_err());
+    }
 }
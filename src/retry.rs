@@ -0,0 +1,390 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use crate::dead_letter::DeadLetter;
+use crate::error::BeanstalkcResult;
+use crate::stats::JobStats;
+use crate::Beanstalkc;
+
+// beanstalkd takes delays as a number of seconds fitting in 32 bits.
+const MAX_DELAY: Duration = Duration::from_secs(u32::MAX as u64);
+
+/// `Backoff` computes the delay before retrying a job, from its attempt number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backoff {
+    /// Always wait the same delay.
+    Fixed(Duration),
+    /// Wait `n` times the delay after the `n`th attempt.
+    Linear(Duration),
+    /// Wait the delay after the first attempt, then twice as long after each one.
+    Exponential(Duration),
+}
+
+impl Backoff {
+    fn delay(&self, attempt: u32) -> Duration {
+        match *self {
+            Backoff::Fixed(delay) => delay,
+            Backoff::Linear(step) => step.checked_mul(attempt).unwrap_or(Duration::MAX),
+            Backoff::Exponential(initial) => 1u32
+                .checked_shl(attempt.saturating_sub(1))
+                .and_then(|factor| initial.checked_mul(factor))
+                .unwrap_or(Duration::MAX),
+        }
+    }
+}
+
+/// `Exhausted` tells what to do with a job which failed `max_attempts` times.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Exhausted {
+    /// Bury the job, to be kicked after inspection.
+    Bury,
+    /// Move the job to its dead-letter tube, with its failure metadata.
+    DeadLetter(DeadLetter),
+}
+
+/// `RetryOutcome` tells what `RetryPolicy::apply` did with a failed job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryOutcome {
+    /// The job was released, to be reserved again after the given delay.
+    Released(Duration),
+    /// The job was buried.
+    Buried,
+    /// The job was moved to its dead-letter tube, under the given id.
+    Moved(u64),
+}
+
+/// `RetryPolicy` spaces the retries of a failed job, and decides what to do with it
+/// once it failed too many times.
+///
+/// The attempt number of a job is its `reserves` count from `stats-job`, so that it
+/// survives the job being reserved by another worker. beanstalkd counts delays in
+/// whole seconds, so delays are rounded down.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use beanstalkc::{Beanstalkc, DeadLetter, RetryPolicy};
+///
+/// let policy = RetryPolicy::exponential(Duration::from_secs(2))
+///     .max_delay(Duration::from_secs(600))
+///     .jitter(0.2)
+///     .max_attempts(8)
+///     .dead_letter_when_exhausted(DeadLetter::tube("failed"));
+///
+/// let mut conn = Beanstalkc::new().connect().unwrap();
+/// let mut job = conn.reserve().unwrap().into_job().unwrap();
+/// // Failed to execute job...
+/// job.retry(&policy).unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    backoff: Backoff,
+    max_delay: Duration,
+    jitter: f64,
+    max_attempts: u32,
+    exhausted: Exhausted,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::exponential(Duration::from_secs(1))
+    }
+}
+
+impl RetryPolicy {
+    /// Create a policy with the given backoff, making 5 attempts, waiting at most
+    /// one hour between them, and burying the job once they are exhausted.
+    pub fn new(backoff: Backoff) -> RetryPolicy {
+        RetryPolicy {
+            backoff,
+            max_delay: Duration::from_secs(3600),
+            jitter: 0.0,
+            max_attempts: 5,
+            exhausted: Exhausted::Bury,
+        }
+    }
+
+    /// Create a policy waiting the same delay before every retry.
+    pub fn fixed(delay: Duration) -> RetryPolicy {
+        RetryPolicy::new(Backoff::Fixed(delay))
+    }
+
+    /// Create a policy waiting `n` times `step` after the `n`th attempt.
+    pub fn linear(step: Duration) -> RetryPolicy {
+        RetryPolicy::new(Backoff::Linear(step))
+    }
+
+    /// Create a policy waiting `initial` after the first attempt, then twice as long
+    /// after each one.
+    pub fn exponential(initial: Duration) -> RetryPolicy {
+        RetryPolicy::new(Backoff::Exponential(initial))
+    }
+
+    /// Set the upper bound of the delay. Default is `3600s`, and delays never exceed
+    /// the `u32::MAX` seconds beanstalkd accepts.
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay.min(MAX_DELAY);
+        self
+    }
+
+    /// Shorten each delay by a random fraction of up to `jitter`, between `0` and
+    /// `1`, so that jobs failed together are not retried together. Default is `0`.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = if jitter.is_nan() {
+            0.0
+        } else {
+            jitter.clamp(0.0, 1.0)
+        };
+        self
+    }
+
+    /// Set how many times a job may be attempted in total. Default is `5`.
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts;
+        self
+    }
+
+    /// Bury the jobs exhausting their attempts. This is the default.
+    pub fn bury_when_exhausted(mut self) -> Self {
+        self.exhausted = Exhausted::Bury;
+        self
+    }
+
+    /// Move the jobs exhausting their attempts to their dead-letter tube.
+    pub fn dead_letter_when_exhausted(mut self, dead_letter: DeadLetter) -> Self {
+        self.exhausted = Exhausted::DeadLetter(dead_letter);
+        self
+    }
+
+    /// Return the attempt number of a reserved job, counting from 1.
+    pub fn attempt(stats: &JobStats) -> u32 {
+        // Every reservation counts, including those which ended with the TTR
+        // expiring rather than with a release.
+        stats.reserves.clamp(1, u64::from(u32::MAX)) as u32
+    }
+
+    /// Return the delay before retrying a job, or `None` once its attempts are
+    /// exhausted.
+    pub fn next_delay(&self, stats: &JobStats) -> Option<Duration> {
+        let attempt = RetryPolicy::attempt(stats);
+        if attempt >= self.max_attempts {
+            return None;
+        }
+
+        let delay = self
+            .backoff
+            .delay(attempt)
+            .min(self.max_delay)
+            .min(MAX_DELAY);
+        Some(delay.mul_f64(1.0 - self.jitter * random_fraction()))
+    }
+
+    /// Retry a failed job reserved by `conn`: release it after the backoff delay, or
+    /// bury it or move it to its dead-letter tube once its attempts are exhausted.
+    pub fn apply(
+        &self,
+        conn: &mut Beanstalkc,
+        job_id: u64,
+        body: &[u8],
+    ) -> BeanstalkcResult<RetryOutcome> {
+        let stats = conn.stats_job(job_id)?;
+        if let Some(delay) = self.next_delay(&stats) {
+            conn.release(job_id, stats.pri, delay)?;
+            return Ok(RetryOutcome::Released(delay));
+        }
+
+        match &self.exhausted {
+            Exhausted::Bury => {
+                conn.bury(job_id, stats.pri)?;
+                Ok(RetryOutcome::Buried)
+            }
+            Exhausted::DeadLetter(dead_letter) => {
+                let attempts = RetryPolicy::attempt(&stats);
+                let reason = match attempts {
+                    1 => "failed after 1 attempt".to_string(),
+                    n => format!("failed after {} attempts", n),
+                };
+                let id = dead_letter.send(conn, job_id, body, &reason)?;
+                Ok(RetryOutcome::Moved(id))
+            }
+        }
+    }
+}
+
+/// Return a random number in `[0, 1)`, seeded by the randomly keyed std hasher.
+fn random_fraction() -> f64 {
+    let bits = RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{reserved_job_stats, MockServer};
+    use crate::stats::JobState;
+
+    fn stats(reserves: u64, releases: u64) -> JobStats {
+        JobStats {
+            reserves,
+            releases,
+            ..reserved_job_stats()
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let second = Duration::from_secs(1);
+        let delays = |policy: RetryPolicy| -> Vec<Option<Duration>> {
+            (1..=5)
+                .map(|n| policy.next_delay(&stats(n, n - 1)))
+                .collect()
+        };
+
+        let fixed = RetryPolicy::fixed(second * 3).max_attempts(4);
+        assert_eq!(
+            vec![
+                Some(second * 3),
+                Some(second * 3),
+                Some(second * 3),
+                None,
+                None
+            ],
+            delays(fixed)
+        );
+        let linear = RetryPolicy::linear(second * 2).max_attempts(4);
+        assert_eq!(
+            vec![
+                Some(second * 2),
+                Some(second * 4),
+                Some(second * 6),
+                None,
+                None
+            ],
+            delays(linear)
+        );
+        let exponential = RetryPolicy::exponential(second)
+            .max_delay(second * 6)
+            .max_attempts(6);
+        assert_eq!(
+            vec![
+                Some(second),
+                Some(second * 2),
+                Some(second * 4),
+                Some(second * 6),
+                Some(second * 6)
+            ],
+            delays(exponential)
+        );
+        let overflowing = RetryPolicy::exponential(second).max_attempts(100);
+        assert_eq!(
+            Some(Duration::from_secs(3600)),
+            overflowing.next_delay(&stats(80, 79))
+        );
+        let unbounded = RetryPolicy::exponential(second)
+            .max_delay(Duration::MAX)
+            .jitter(0.5)
+            .max_attempts(100);
+        let delay = unbounded.next_delay(&stats(80, 79)).unwrap();
+        assert!(delay <= MAX_DELAY && delay >= MAX_DELAY / 2);
+        let unbounded = RetryPolicy::linear(Duration::MAX).max_delay(Duration::MAX);
+        assert_eq!(Some(MAX_DELAY), unbounded.next_delay(&stats(2, 1)));
+    }
+
+    #[test]
+    fn test_attempt() {
+        assert_eq!(1, RetryPolicy::attempt(&stats(1, 0)));
+        // Released once, then timed out once.
+        assert_eq!(3, RetryPolicy::attempt(&stats(3, 1)));
+        assert_eq!(1, RetryPolicy::attempt(&stats(0, 0)));
+    }
+
+    #[test]
+    fn test_jitter() {
+        let policy = RetryPolicy::fixed(Duration::from_secs(100)).jitter(0.5);
+        for _ in 0..100 {
+            let delay = policy.next_delay(&stats(1, 0)).unwrap();
+            assert!(delay > Duration::from_secs(50) && delay <= Duration::from_secs(100));
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+        let policy = RetryPolicy::exponential(Duration::from_secs(10)).max_attempts(2);
+
+        let id = conn
+            .put(b"job", 7, Duration::from_secs(0), Duration::from_secs(60))
+            .unwrap();
+        let job = conn.reserve().unwrap().into_job().unwrap().detach();
+        assert_eq!(
+            RetryOutcome::Released(Duration::from_secs(10)),
+            policy.apply(&mut conn, id, job.body()).unwrap()
+        );
+        let stats = conn.stats_job(id).unwrap();
+        assert_eq!(JobState::Delayed, stats.state);
+        assert_eq!(7, stats.pri);
+
+        conn.kick_job(id).unwrap();
+        conn.reserve().unwrap();
+        assert_eq!(
+            RetryOutcome::Buried,
+            policy.apply(&mut conn, id, job.body()).unwrap()
+        );
+        assert_eq!(JobState::Buried, conn.stats_job(id).unwrap().state);
+    }
+
+    #[test]
+    fn test_apply_dead_letters_job() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+        let policy = RetryPolicy::fixed(Duration::from_secs(0))
+            .max_attempts(1)
+            .dead_letter_when_exhausted(DeadLetter::new());
+
+        let id = conn.put_default(b"job").unwrap();
+        conn.reserve().unwrap();
+        let moved = match policy.apply(&mut conn, id, b"job").unwrap() {
+            RetryOutcome::Moved(moved) => moved,
+            outcome => panic!("unexpected outcome {:?}", outcome),
+        };
+
+        assert!(conn.peek(id).is_err());
+        assert_eq!("default.dead", conn.stats_job(moved).unwrap().tube);
+        let letter = conn.peek(moved).unwrap().body().to_vec();
+        let (info, body) = DeadLetter::decode(&letter).unwrap();
+        assert_eq!(b"job", body);
+        assert_eq!("failed after 1 attempt", info.reason);
+    }
+
+    #[test]
+    fn test_apply_moves_job() {
+        let server = MockServer::start();
+        let mut conn = server.connect();
+        conn.use_tube("jobs").unwrap();
+        conn.watch("jobs").unwrap();
+        let policy = RetryPolicy::fixed(Duration::from_secs(0))
+            .max_attempts(1)
+            .dead_letter_when_exhausted(DeadLetter::tube("failed"));
+
+        let id = conn
+            .put(b"job", 7, Duration::from_secs(0), Duration::from_secs(30))
+            .unwrap();
+        conn.reserve().unwrap();
+        let moved = match policy.apply(&mut conn, id, b"job").unwrap() {
+            RetryOutcome::Moved(moved) => moved,
+            outcome => panic!("unexpected outcome {:?}", outcome),
+        };
+
+        assert!(conn.peek(id).is_err());
+        let stats = conn.stats_job(moved).unwrap();
+        assert_eq!("failed", stats.tube);
+        assert_eq!(7, stats.pri);
+        assert_eq!(Duration::from_secs(30), stats.ttr);
+        let letter = conn.peek(moved).unwrap().body().to_vec();
+        assert_eq!(b"job", DeadLetter::decode(&letter).unwrap().1);
+        assert_eq!("jobs", conn.using().unwrap());
+    }
+}
@@ -0,0 +1,515 @@
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::{BeanstalkcError, BeanstalkcResult};
+use crate::job::{Outcome, OwnedJob, ReserveOutcome};
+use crate::stats::JobStats;
+use crate::Beanstalkc;
+
+// Points per shard on the hash ring, which evens out the share of each shard.
+const VIRTUAL_NODES: usize = 160;
+// Default wait between two rounds of `ShardedClient::reserve_with_timeout`. Unlike
+// `DEFAULT_POLL_INTERVAL`, which bounds a blocking reserve, it delays every job
+// becoming ready while the shards are idle, hence a shorter value.
+const SHARD_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// 64-bit FNV-1a hash, which is stable across builds and platforms unlike the
+/// standard library hasher.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// Return the position of a key on the hash ring.
+///
+/// FNV-1a alone barely changes the high bits of keys differing by their last bytes,
+/// like `user-1` and `user-2`, so the MurmurHash3 finalizer spreads them out.
+fn ring_position(key: &[u8]) -> u64 {
+    let mut hash = fnv1a(key);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
+
+/// `JobHandle` identifies a job put through or reserved from a `ShardedClient`:
+/// the shard it lives on, and its id on that shard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct JobHandle {
+    shard: usize,
+    id: u64,
+}
+
+impl JobHandle {
+    /// Return the index of the shard holding the job.
+    pub fn shard(&self) -> usize {
+        self.shard
+    }
+
+    /// Return the job id on its shard. Ids are only unique within a shard.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl fmt::Display for JobHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.shard, self.id)
+    }
+}
+
+/// `ShardedJob` is a job reserved from a `ShardedClient`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShardedJob {
+    handle: JobHandle,
+    job: OwnedJob,
+}
+
+impl ShardedJob {
+    /// Return the handle to pass to the `ShardedClient` methods.
+    pub fn handle(&self) -> JobHandle {
+        self.handle
+    }
+
+    /// Return the index of the shard holding the job.
+    pub fn shard(&self) -> usize {
+        self.handle.shard
+    }
+
+    /// Return the job id on its shard.
+    pub fn id(&self) -> u64 {
+        self.handle.id
+    }
+
+    /// Return job body.
+    pub fn body(&self) -> &[u8] {
+        self.job.body()
+    }
+
+    /// Return the job, without its shard.
+    pub fn into_job(self) -> OwnedJob {
+        self.job
+    }
+}
+
+/// `ShardedReserveOutcome` is the result of a reservation by a `ShardedClient`.
+// Holds the job inline like `ReserveOutcome`, since it is matched on right away.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum ShardedReserveOutcome {
+    /// A job was reserved.
+    Job(ShardedJob),
+    /// A job reserved by this client from one of the shards is about to exceed its
+    /// TTR. Delete, release or touch it before reserving again.
+    DeadlineSoon,
+    /// No job became available on any shard before the timeout expired.
+    TimedOut,
+}
+
+impl ShardedReserveOutcome {
+    /// Return the reserved job, if any.
+    pub fn job(self) -> Option<ShardedJob> {
+        match self {
+            ShardedReserveOutcome::Job(job) => Some(job),
+            _ => None,
+        }
+    }
+
+    /// Return the reserved job, turning `DeadlineSoon` and `TimedOut` into errors.
+    pub fn into_job(self) -> BeanstalkcResult<ShardedJob> {
+        match self {
+            ShardedReserveOutcome::Job(job) => Ok(job),
+            ShardedReserveOutcome::DeadlineSoon => Err(BeanstalkcError::DeadlineSoon),
+            ShardedReserveOutcome::TimedOut => Err(BeanstalkcError::TimedOut),
+        }
+    }
+}
+
+/// `ShardedClient` spreads jobs over several independent beanstalkd servers.
+///
+/// Puts are routed by consistent hashing on the tube name, or on a key given by the
+/// caller, so that adding or removing a server only moves the keys of that server.
+/// Servers are placed on the ring by address, so every client given the same servers
+/// routes the same way whatever their order.
+///
+/// Reservations go round-robin over all the shards, and the returned jobs carry the
+/// shard they came from, so that they are deleted or released on the right server.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use beanstalkc::{Beanstalkc, ShardedClient};
+///
+/// let shards = vec![
+///     Beanstalkc::new().host("10.0.0.1").connect().unwrap(),
+///     Beanstalkc::new().host("10.0.0.2").connect().unwrap(),
+/// ];
+/// let mut client = ShardedClient::new(shards);
+///
+/// client
+///     .put("emails", b"hello", 0, Duration::from_secs(0), Duration::from_secs(60))
+///     .unwrap();
+///
+/// client.watch("emails").unwrap();
+/// if let Some(job) = client.reserve_with_timeout(Duration::from_secs(5)).unwrap().job() {
+///     // Execute job...
+///     client.delete(job.handle()).unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ShardedClient {
+    shards: Vec<Beanstalkc>,
+    // Sorted points of the hash ring, with the shard owning each point.
+    ring: Vec<(u64, usize)>,
+    next: usize,
+    poll_interval: Duration,
+}
+
+impl ShardedClient {
+    /// Create a client over the given connections.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is empty.
+    pub fn new(shards: Vec<Beanstalkc>) -> ShardedClient {
+        assert!(!shards.is_empty(), "at least one shard is required");
+
+        let mut ring = Vec::with_capacity(shards.len() * VIRTUAL_NODES);
+        for (index, conn) in shards.iter().enumerate() {
+            let address = conn.address();
+            for node in 0..VIRTUAL_NODES {
+                let point = ring_position(format!("{}-{}", address, node).as_bytes());
+                ring.push((point, index));
+            }
+        }
+        ring.sort_unstable();
+
+        ShardedClient {
+            shards,
+            ring,
+            next: 0,
+            poll_interval: SHARD_POLL_INTERVAL,
+        }
+    }
+
+    /// Set how long `reserve_with_timeout` waits between two rounds over the shards
+    /// finding no job. Default is `100ms`.
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Return the number of shards.
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Return the connection of the given shard.
+    pub fn connection(&mut self, shard: usize) -> Option<&mut Beanstalkc> {
+        self.shards.get_mut(shard)
+    }
+
+    /// Return the shard owning the given key.
+    pub fn shard_for(&self, key: &[u8]) -> usize {
+        let hash = ring_position(key);
+        let index = self.ring.partition_point(|(point, _)| *point < hash);
+        self.ring[index % self.ring.len()].1
+    }
+
+    /// Put a job into the given tube, on the shard owning the tube name.
+    pub fn put(
+        &mut self,
+        tube: &str,
+        body: &[u8],
+        priority: u32,
+        delay: Duration,
+        ttr: Duration,
+    ) -> BeanstalkcResult<JobHandle> {
+        let shard = self.shard_for(tube.as_bytes());
+        self.put_on(shard, tube, body, priority, delay, ttr)
+    }
+
+    /// Put a job into the given tube, on the shard owning `key`. This spreads the
+    /// jobs of a busy tube over several shards.
+    pub fn put_with_key(
+        &mut self,
+        key: &[u8],
+        tube: &str,
+        body: &[u8],
+        priority: u32,
+        delay: Duration,
+        ttr: Duration,
+    ) -> BeanstalkcResult<JobHandle> {
+        let shard = self.shard_for(key);
+        self.put_on(shard, tube, body, priority, delay, ttr)
+    }
+
+    fn put_on(
+        &mut self,
+        shard: usize,
+        tube: &str,
+        body: &[u8],
+        priority: u32,
+        delay: Duration,
+        ttr: Duration,
+    ) -> BeanstalkcResult<JobHandle> {
+        let conn = &mut self.shards[shard];
+        if conn.current_tube() != tube {
+            conn.use_tube(tube)?;
+        }
+        let id = conn.put(body, priority, delay, ttr)?;
+        Ok(JobHandle { shard, id })
+    }
+
+    /// Watch the given tube on every shard.
+    pub fn watch(&mut self, name: &str) -> BeanstalkcResult<()> {
+        for conn in &mut self.shards {
+            conn.watch(name)?;
+        }
+        Ok(())
+    }
+
+    /// Ignore the given tube on every shard.
+    pub fn ignore(&mut self, name: &str) -> BeanstalkcResult<()> {
+        for conn in &mut self.shards {
+            conn.ignore(name)?;
+        }
+        Ok(())
+    }
+
+    /// Reserve a job from the watched tubes of the shards, taking turns.
+    ///
+    /// Shards are polled without blocking, waiting `poll_interval` between rounds,
+    /// until a job is found or `timeout` expires. Return
+    /// `ShardedReserveOutcome::TimedOut` in the latter case, and
+    /// `ShardedReserveOutcome::DeadlineSoon` if a job reserved by this client is about
+    /// to exceed its TTR.
+    pub fn reserve_with_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> BeanstalkcResult<ShardedReserveOutcome> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            for _ in 0..self.shards.len() {
+                let shard = self.next;
+                self.next = (self.next + 1) % self.shards.len();
+                let conn = &mut self.shards[shard];
+                match conn.reserve_with_timeout(Duration::from_secs(0))? {
+                    ReserveOutcome::Job(job) => {
+                        let handle = JobHandle {
+                            shard,
+                            id: job.id(),
+                        };
+                        let job = job.detach();
+                        return Ok(ShardedReserveOutcome::Job(ShardedJob { handle, job }));
+                    }
+                    ReserveOutcome::DeadlineSoon => return Ok(ShardedReserveOutcome::DeadlineSoon),
+                    ReserveOutcome::TimedOut => {}
+                }
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(ShardedReserveOutcome::TimedOut);
+            }
+            thread::sleep(self.poll_interval.min(deadline - now));
+        }
+    }
+
+    /// Delete a job from its shard.
+    pub fn delete(&mut self, handle: JobHandle) -> BeanstalkcResult<()> {
+        self.shards[handle.shard].delete(handle.id)
+    }
+
+    /// Release a reserved job back to its shard.
+    pub fn release(
+        &mut self,
+        handle: JobHandle,
+        priority: u32,
+        delay: Duration,
+    ) -> BeanstalkcResult<()> {
+        self.shards[handle.shard].release(handle.id, priority, delay)
+    }
+
+    /// Bury a reserved job on its shard.
+    pub fn bury(&mut self, handle: JobHandle, priority: u32) -> BeanstalkcResult<()> {
+        self.shards[handle.shard].bury(handle.id, priority)
+    }
+
+    /// Touch a reserved job on its shard.
+    pub fn touch(&mut self, handle: JobHandle) -> BeanstalkcResult<()> {
+        self.shards[handle.shard].touch(handle.id)
+    }
+
+    /// Kick a buried or delayed job on its shard.
+    pub fn kick_job(&mut self, handle: JobHandle) -> BeanstalkcResult<()> {
+        self.shards[handle.shard].kick_job(handle.id)
+    }
+
+    /// Return the statistical information about a job from its shard.
+    pub fn stats_job(&mut self, handle: JobHandle) -> BeanstalkcResult<JobStats> {
+        self.shards[handle.shard].stats_job(handle.id)
+    }
+
+    /// Delete, release or bury a reserved job on its shard.
+    pub fn finish(&mut self, job: ShardedJob, outcome: Outcome) -> BeanstalkcResult<()> {
+        self.shards[job.handle.shard].finish(job.job, outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockServer;
+
+    fn put(client: &mut ShardedClient, tube: &str, body: &[u8]) -> JobHandle {
+        client
+            .put(
+                tube,
+                body,
+                0,
+                Duration::from_secs(0),
+                Duration::from_secs(60),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn test_fnv1a() {
+        assert_eq!(0xcbf2_9ce4_8422_2325, fnv1a(b""));
+        assert_eq!(0xaf63_dc4c_8601_ec8c, fnv1a(b"a"));
+        assert_eq!(0x8594_4171_f739_67e8, fnv1a(b"foobar"));
+    }
+
+    #[test]
+    fn test_put_routes_by_tube() {
+        let servers: Vec<_> = (0..3).map(|_| MockServer::start()).collect();
+        let mut client = ShardedClient::new(servers.iter().map(|x| x.connect()).collect());
+
+        let first = put(&mut client, "emails", b"first");
+        let second = put(&mut client, "emails", b"second");
+        assert_eq!(first.shard(), second.shard());
+        assert_eq!(client.shard_for(b"emails"), first.shard());
+
+        let mut conn = servers[first.shard()].connect();
+        assert_eq!("emails", conn.stats_job(second.id()).unwrap().tube);
+    }
+
+    #[test]
+    fn test_put_with_key_spreads_jobs() {
+        let servers: Vec<_> = (0..3).map(|_| MockServer::start()).collect();
+        let mut client = ShardedClient::new(servers.iter().map(|x| x.connect()).collect());
+
+        let mut counts = vec![0; 3];
+        for key in 0..300 {
+            let handle = client
+                .put_with_key(
+                    format!("user-{}", key).as_bytes(),
+                    "jobs",
+                    b"job",
+                    0,
+                    Duration::from_secs(0),
+                    Duration::from_secs(60),
+                )
+                .unwrap();
+            counts[handle.shard()] += 1;
+        }
+        assert!(counts.iter().all(|count| *count > 50), "{:?}", counts);
+    }
+
+    #[test]
+    fn test_consistent_hashing() {
+        let servers: Vec<_> = (0..4).map(|_| MockServer::start()).collect();
+        let three = ShardedClient::new(servers[..3].iter().map(|x| x.connect()).collect());
+        let four = ShardedClient::new(servers.iter().map(|x| x.connect()).collect());
+        let reversed = ShardedClient::new(servers[..3].iter().rev().map(|x| x.connect()).collect());
+
+        for key in 0..1000 {
+            let key = format!("key-{}", key);
+            let shard = four.shard_for(key.as_bytes());
+            if shard != 3 {
+                assert_eq!(three.shard_for(key.as_bytes()), shard);
+            }
+            assert_eq!(
+                2 - three.shard_for(key.as_bytes()),
+                reversed.shard_for(key.as_bytes())
+            );
+        }
+    }
+
+    #[test]
+    fn test_reserve_round_robin() {
+        let servers: Vec<_> = (0..2).map(|_| MockServer::start()).collect();
+        for server in &servers {
+            let mut conn = server.connect();
+            conn.put_default(b"a").unwrap();
+            conn.put_default(b"b").unwrap();
+        }
+
+        let mut client = ShardedClient::new(servers.iter().map(|x| x.connect()).collect())
+            .poll_interval(Duration::from_millis(10));
+        let mut shards = vec![];
+        for _ in 0..4 {
+            let job = client
+                .reserve_with_timeout(Duration::from_secs(1))
+                .unwrap()
+                .into_job()
+                .unwrap();
+            shards.push(job.shard());
+            client.delete(job.handle()).unwrap();
+        }
+        assert_eq!(vec![0, 1, 0, 1], shards);
+
+        assert!(matches!(
+            client.reserve_with_timeout(Duration::from_millis(50)),
+            Ok(ShardedReserveOutcome::TimedOut)
+        ));
+    }
+
+    #[test]
+    fn test_handle_goes_back_to_its_shard() {
+        let servers: Vec<_> = (0..2).map(|_| MockServer::start()).collect();
+        // Both servers give the id 1 to their first job.
+        servers[1].connect().put_default(b"second").unwrap();
+        servers[0].connect().put_default(b"first").unwrap();
+
+        let mut client = ShardedClient::new(servers.iter().map(|x| x.connect()).collect());
+        client.next = 1;
+        let job = client
+            .reserve_with_timeout(Duration::from_secs(0))
+            .unwrap()
+            .job()
+            .unwrap();
+        assert_eq!(1, job.shard());
+        assert_eq!(b"second", job.body());
+        assert_eq!("1/1", job.handle().to_string());
+
+        client.finish(job, Outcome::Delete).unwrap();
+        assert!(servers[1].connect().peek(1).is_err());
+        assert!(servers[0].connect().peek(1).is_ok());
+    }
+
+    #[test]
+    fn test_reserve_deadline_soon() {
+        let server = MockServer::start();
+        server
+            .connect()
+            .put(b"job", 0, Duration::from_secs(0), Duration::from_secs(1))
+            .unwrap();
+
+        let mut client = ShardedClient::new(vec![server.connect()]);
+        let job = client.reserve_with_timeout(Duration::from_secs(0)).unwrap();
+        assert!(job.job().is_some());
+        assert!(matches!(
+            client.reserve_with_timeout(Duration::from_secs(0)),
+            Ok(ShardedReserveOutcome::DeadlineSoon)
+        ));
+    }
+}
@@ -0,0 +1,28 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// `ShutdownHandle` asks a consume loop to stop reserving new jobs.
+///
+/// Handles are cheap to clone and can be sent to other threads or tasks, e.g. a
+/// signal handler.
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownHandle {
+    requested: Arc<AtomicBool>,
+}
+
+impl ShutdownHandle {
+    /// Create a new `ShutdownHandle`.
+    pub fn new() -> ShutdownHandle {
+        ShutdownHandle::default()
+    }
+
+    /// Request the shutdown. The consume loop notices it before its next reservation.
+    pub fn shutdown(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+    }
+
+    /// Return whether the shutdown was requested.
+    pub fn is_shutdown(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,270 @@
+use std::fmt;
+use std::time::Duration;
+
+use serde::{Deserialize, Deserializer};
+
+/// Lifecycle state of a job as reported by `stats-job`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Ready,
+    Delayed,
+    Reserved,
+    Buried,
+}
+
+impl fmt::Display for JobState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = match self {
+            JobState::Ready => "ready",
+            JobState::Delayed => "delayed",
+            JobState::Reserved => "reserved",
+            JobState::Buried => "buried",
+        };
+        write!(f, "{}", state)
+    }
+}
+
+/// Statistical information about a job, returned by `stats-job`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct JobStats {
+    /// The job id.
+    pub id: u64,
+    /// The name of the tube that contains this job.
+    pub tube: String,
+    pub state: JobState,
+    /// The priority set by the put, release, or bury commands.
+    pub pri: u32,
+    /// Time since the put command that created this job.
+    #[serde(deserialize_with = "deserialize_secs")]
+    pub age: Duration,
+    /// Time to wait before putting this job in the ready queue.
+    #[serde(deserialize_with = "deserialize_secs")]
+    pub delay: Duration,
+    /// Time a worker is allowed to run this job.
+    #[serde(deserialize_with = "deserialize_secs")]
+    pub ttr: Duration,
+    /// Time left until the server puts this job into the ready queue.
+    /// Only meaningful if the job is reserved or delayed.
+    #[serde(deserialize_with = "deserialize_secs")]
+    pub time_left: Duration,
+    /// The number of the earliest binlog file containing this job.
+    pub file: u64,
+    pub reserves: u64,
+    pub timeouts: u64,
+    pub releases: u64,
+    pub buries: u64,
+    pub kicks: u64,
+}
+
+/// Statistical information about a tube, returned by `stats-tube`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TubeStats {
+    pub name: String,
+    /// The number of ready jobs with priority < 1024.
+    pub current_jobs_urgent: u64,
+    pub current_jobs_ready: u64,
+    pub current_jobs_reserved: u64,
+    pub current_jobs_delayed: u64,
+    pub current_jobs_buried: u64,
+    /// Cumulative count of jobs created in this tube.
+    pub total_jobs: u64,
+    pub current_using: u64,
+    pub current_waiting: u64,
+    pub current_watching: u64,
+    /// How long the tube has been paused for.
+    #[serde(deserialize_with = "deserialize_secs")]
+    pub pause: Duration,
+    pub cmd_delete: u64,
+    pub cmd_pause_tube: u64,
+    /// Time left until the tube is un-paused.
+    #[serde(deserialize_with = "deserialize_secs")]
+    pub pause_time_left: Duration,
+}
+
+/// Statistical information about the server, returned by `stats`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ServerStats {
+    pub current_jobs_urgent: u64,
+    pub current_jobs_ready: u64,
+    pub current_jobs_reserved: u64,
+    pub current_jobs_delayed: u64,
+    pub current_jobs_buried: u64,
+    pub cmd_put: u64,
+    pub cmd_peek: u64,
+    pub cmd_peek_ready: u64,
+    pub cmd_peek_delayed: u64,
+    pub cmd_peek_buried: u64,
+    pub cmd_reserve: u64,
+    pub cmd_use: u64,
+    pub cmd_watch: u64,
+    pub cmd_ignore: u64,
+    pub cmd_delete: u64,
+    pub cmd_release: u64,
+    pub cmd_bury: u64,
+    pub cmd_kick: u64,
+    pub cmd_stats: u64,
+    pub cmd_stats_job: u64,
+    pub cmd_stats_tube: u64,
+    pub cmd_list_tubes: u64,
+    pub cmd_list_tube_used: u64,
+    pub cmd_list_tubes_watched: u64,
+    pub cmd_pause_tube: u64,
+    pub job_timeouts: u64,
+    pub total_jobs: u64,
+    /// The maximum number of bytes in a job.
+    pub max_job_size: u64,
+    pub current_tubes: u64,
+    pub current_connections: u64,
+    pub current_producers: u64,
+    pub current_workers: u64,
+    pub current_waiting: u64,
+    pub total_connections: u64,
+    pub pid: u64,
+    pub version: String,
+    #[serde(deserialize_with = "deserialize_fractional_secs")]
+    pub rusage_utime: Duration,
+    #[serde(deserialize_with = "deserialize_fractional_secs")]
+    pub rusage_stime: Duration,
+    #[serde(deserialize_with = "deserialize_secs")]
+    pub uptime: Duration,
+    pub binlog_oldest_index: u64,
+    pub binlog_current_index: u64,
+    pub binlog_max_size: u64,
+    pub binlog_records_written: u64,
+    pub binlog_records_migrated: u64,
+    /// A random id string for this server process.
+    pub id: String,
+    pub hostname: String,
+}
+
+fn deserialize_secs<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    u64::deserialize(deserializer).map(Duration::from_secs)
+}
+
+fn deserialize_fractional_secs<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let secs = f64::deserialize(deserializer)?;
+    if !secs.is_finite() || secs < 0.0 {
+        return Err(serde::de::Error::custom(format!(
+            "invalid duration: {}",
+            secs
+        )));
+    }
+    Ok(Duration::from_secs_f64(secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_stats() {
+        let yaml = "---\nid: 1\ntube: default\nstate: reserved\npri: 1024\nage: 12\n\
+                    delay: 0\nttr: 60\ntime-left: 59\nfile: 0\nreserves: 1\n\
+                    timeouts: 0\nreleases: 0\nburies: 0\nkicks: 0\n";
+        let stats: JobStats = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(1, stats.id);
+        assert_eq!("default", stats.tube);
+        assert_eq!(JobState::Reserved, stats.state);
+        assert_eq!(1024, stats.pri);
+        assert_eq!(Duration::from_secs(60), stats.ttr);
+        assert_eq!(Duration::from_secs(59), stats.time_left);
+        assert_eq!(1, stats.reserves);
+    }
+
+    #[test]
+    fn test_job_stats_invalid_state() {
+        let yaml = "---\nid: 1\ntube: default\nstate: lost\npri: 1024\nage: 12\n\
+                    delay: 0\nttr: 60\ntime-left: 59\nfile: 0\nreserves: 1\n\
+                    timeouts: 0\nreleases: 0\nburies: 0\nkicks: 0\n";
+        assert!(serde_yaml::from_str::<JobStats>(yaml).is_err());
+    }
+
+    #[test]
+    fn test_tube_stats() {
+        let yaml = "---\nname: jobs\ncurrent-jobs-urgent: 0\ncurrent-jobs-ready: 2\n\
+                    current-jobs-reserved: 1\ncurrent-jobs-delayed: 0\n\
+                    current-jobs-buried: 3\ntotal-jobs: 6\ncurrent-using: 1\n\
+                    current-waiting: 0\ncurrent-watching: 1\npause: 0\n\
+                    cmd-delete: 0\ncmd-pause-tube: 0\npause-time-left: 0\n";
+        let stats: TubeStats = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!("jobs", stats.name);
+        assert_eq!(2, stats.current_jobs_ready);
+        assert_eq!(3, stats.current_jobs_buried);
+        assert_eq!(Duration::from_secs(0), stats.pause_time_left);
+    }
+
+    #[test]
+    fn test_tube_stats_missing_field() {
+        let yaml = "---\nname: jobs\ncurrent-jobs-ready: 2\n";
+        assert!(serde_yaml::from_str::<TubeStats>(yaml).is_err());
+    }
+
+    #[test]
+    fn test_server_stats() {
+        let mut yaml = String::from("---\n");
+        for key in &[
+            "current-jobs-urgent",
+            "current-jobs-ready",
+            "current-jobs-reserved",
+            "current-jobs-delayed",
+            "current-jobs-buried",
+            "cmd-put",
+            "cmd-peek",
+            "cmd-peek-ready",
+            "cmd-peek-delayed",
+            "cmd-peek-buried",
+            "cmd-reserve",
+            "cmd-use",
+            "cmd-watch",
+            "cmd-ignore",
+            "cmd-delete",
+            "cmd-release",
+            "cmd-bury",
+            "cmd-kick",
+            "cmd-stats",
+            "cmd-stats-job",
+            "cmd-stats-tube",
+            "cmd-list-tubes",
+            "cmd-list-tube-used",
+            "cmd-list-tubes-watched",
+            "cmd-pause-tube",
+            "job-timeouts",
+            "total-jobs",
+            "current-tubes",
+            "current-connections",
+            "current-producers",
+            "current-workers",
+            "current-waiting",
+            "total-connections",
+            "binlog-oldest-index",
+            "binlog-current-index",
+            "binlog-max-size",
+            "binlog-records-written",
+            "binlog-records-migrated",
+        ] {
+            yaml.push_str(&format!("{}: 0\n", key));
+        }
+        yaml.push_str(
+            "max-job-size: 65535\npid: 42\nversion: \"1.12\"\nrusage-utime: 0.148000\n\
+             rusage-stime: 1.500000\nuptime: 3600\nid: 4a3e2b1c\nhostname: 1234\n",
+        );
+
+        let stats: ServerStats = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(65535, stats.max_job_size);
+        assert_eq!("1.12", stats.version);
+        assert_eq!(Duration::from_millis(148), stats.rusage_utime);
+        assert_eq!(Duration::from_millis(1500), stats.rusage_stime);
+        assert_eq!(Duration::from_secs(3600), stats.uptime);
+        assert_eq!("1234", stats.hostname);
+    }
+}
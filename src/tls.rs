@@ -0,0 +1,352 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+
+use crate::error::{BeanstalkcError, BeanstalkcResult};
+use crate::transport::Transport;
+
+/// `TlsConfig` describes how to secure the connection to a beanstalkd server
+/// sitting behind a TLS terminator such as stunnel.
+///
+/// Certificates and keys are given in PEM format and parsed when connecting.
+///
+/// # Example
+///
+/// ```no_run
+/// use beanstalkc::{Beanstalkc, TlsConfig};
+///
+/// let ca = std::fs::read("ca.pem").unwrap();
+/// let mut conn = Beanstalkc::new()
+///        .host("queue.internal")
+///        .tls(TlsConfig::new().root_certificates_pem(&ca))
+///        .connect()
+///        .unwrap();
+/// ```
+#[derive(Clone, Default)]
+pub struct TlsConfig {
+    root_certificates: Vec<Vec<u8>>,
+    webpki_roots: bool,
+    client_certificate: Option<(Vec<u8>, Vec<u8>)>,
+    server_name: Option<String>,
+}
+
+impl TlsConfig {
+    /// Create a new `TlsConfig` without any trusted root certificate.
+    pub fn new() -> TlsConfig {
+        TlsConfig::default()
+    }
+
+    /// Trust the CA certificates found in the given PEM data.
+    pub fn root_certificates_pem(mut self, pem: &[u8]) -> Self {
+        self.root_certificates.push(pem.to_vec());
+        self
+    }
+
+    /// Trust the Mozilla root certificates bundled with `webpki-roots`.
+    pub fn webpki_roots(mut self) -> Self {
+        self.webpki_roots = true;
+        self
+    }
+
+    /// Authenticate with the given PEM certificate chain and private key.
+    pub fn client_certificate_pem(mut self, cert_chain: &[u8], private_key: &[u8]) -> Self {
+        self.client_certificate = Some((cert_chain.to_vec(), private_key.to_vec()));
+        self
+    }
+
+    /// Verify the server certificate against this name instead of the host.
+    pub fn server_name(mut self, name: &str) -> Self {
+        self.server_name = Some(name.to_string());
+        self
+    }
+
+    /// Wrap a connected TCP stream, completing the TLS handshake within `timeout`.
+    pub(crate) fn connect(
+        &self,
+        host: &str,
+        mut stream: TcpStream,
+        timeout: Option<Duration>,
+    ) -> BeanstalkcResult<Box<dyn Transport>> {
+        let name = self.server_name.as_deref().unwrap_or(host);
+        let server_name = ServerName::try_from(name.to_string())
+            .map_err(|_| tls_error(format!("invalid server name {}", name)))?;
+
+        let mut conn = ClientConnection::new(Arc::new(self.client_config()?), server_name)
+            .map_err(|e| tls_error(e.to_string()))?;
+        let deadline = timeout.map(|x| Instant::now() + x);
+        while conn.is_handshaking() {
+            if let Some(deadline) = deadline {
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left == Duration::from_secs(0) {
+                    return Err(tls_error(format!("handshake with {} timed out", name)));
+                }
+                stream.set_read_timeout(Some(left))?;
+                stream.set_write_timeout(Some(left))?;
+            }
+            conn.complete_io(&mut stream)
+                .map_err(|e| tls_error(format!("handshake with {} failed: {}", name, e)))?;
+        }
+
+        Ok(Box::new(StreamOwned::new(conn, stream)))
+    }
+
+    fn client_config(&self) -> BeanstalkcResult<ClientConfig> {
+        let mut roots = RootCertStore::empty();
+        if self.webpki_roots {
+            roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        }
+        for pem in &self.root_certificates {
+            for cert in parse_certificates(pem)? {
+                roots
+                    .add(cert)
+                    .map_err(|e| tls_error(format!("invalid root certificate: {}", e)))?;
+            }
+        }
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(|e| tls_error(e.to_string()))?
+            .with_root_certificates(roots);
+
+        match &self.client_certificate {
+            Some((cert_chain, private_key)) => {
+                let cert_chain = parse_certificates(cert_chain)?;
+                let private_key = parse_private_key(private_key)?;
+                builder
+                    .with_client_auth_cert(cert_chain, private_key)
+                    .map_err(|e| tls_error(format!("invalid client certificate: {}", e)))
+            }
+            None => Ok(builder.with_no_client_auth()),
+        }
+    }
+}
+
+impl fmt::Debug for TlsConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TlsConfig")
+            .field("root_certificates", &self.root_certificates.len())
+            .field("webpki_roots", &self.webpki_roots)
+            .field("client_certificate", &self.client_certificate.is_some())
+            .field("server_name", &self.server_name)
+            .finish()
+    }
+}
+
+fn parse_certificates(pem: &[u8]) -> BeanstalkcResult<Vec<CertificateDer<'static>>> {
+    let certs = rustls_pemfile::certs(&mut &pem[..])
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| tls_error(format!("invalid certificate PEM: {}", e)))?;
+    if certs.is_empty() {
+        return Err(tls_error("no certificate found in PEM".to_string()));
+    }
+    Ok(certs)
+}
+
+fn parse_private_key(pem: &[u8]) -> BeanstalkcResult<PrivateKeyDer<'static>> {
+    match rustls_pemfile::private_key(&mut &pem[..]) {
+        Ok(Some(key)) => Ok(key),
+        Ok(None) => Err(tls_error("no private key found in PEM".to_string())),
+        Err(e) => Err(tls_error(format!("invalid private key PEM: {}", e))),
+    }
+}
+
+fn tls_error(msg: String) -> BeanstalkcError {
+    BeanstalkcError::ConnectionError(format!("TLS: {}", msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use rcgen::{
+        BasicConstraints, CertificateParams, CertifiedKey, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+    };
+    use rustls::server::WebPkiClientVerifier;
+    use rustls::ServerConfig;
+
+    use std::net::TcpListener;
+    use std::thread;
+
+    use super::*;
+    use crate::mock::MockServer;
+    use crate::Beanstalkc;
+
+    struct Authority {
+        ca: CertifiedKey,
+    }
+
+    impl Authority {
+        fn new() -> Authority {
+            let key_pair = KeyPair::generate().unwrap();
+            let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let cert = params.self_signed(&key_pair).unwrap();
+            Authority {
+                ca: CertifiedKey { cert, key_pair },
+            }
+        }
+
+        fn issue(&self, name: &str, usage: ExtendedKeyUsagePurpose) -> CertifiedKey {
+            let key_pair = KeyPair::generate().unwrap();
+            let mut params = CertificateParams::new(vec![name.to_string()]).unwrap();
+            params.extended_key_usages = vec![usage];
+            let cert = params
+                .signed_by(&key_pair, &self.ca.cert, &self.ca.key_pair)
+                .unwrap();
+            CertifiedKey { cert, key_pair }
+        }
+
+        fn pem(&self) -> Vec<u8> {
+            self.ca.cert.pem().into_bytes()
+        }
+
+        fn roots(&self) -> RootCertStore {
+            let mut roots = RootCertStore::empty();
+            roots.add(self.ca.cert.der().clone()).unwrap();
+            roots
+        }
+    }
+
+    fn server_config(authority: &Authority, client_roots: Option<RootCertStore>) -> ServerConfig {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let server = authority.issue("localhost", ExtendedKeyUsagePurpose::ServerAuth);
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .unwrap();
+        let builder = match client_roots {
+            Some(roots) => builder.with_client_cert_verifier(
+                WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                    .build()
+                    .unwrap(),
+            ),
+            None => builder.with_no_client_auth(),
+        };
+        builder
+            .with_single_cert(
+                vec![server.cert.der().clone()],
+                PrivateKeyDer::Pkcs8(server.key_pair.serialize_der().into()),
+            )
+            .unwrap()
+    }
+
+    fn connect(server: &MockServer, config: TlsConfig) -> BeanstalkcResult<Beanstalkc> {
+        Beanstalkc::new()
+            .host("127.0.0.1")
+            .port(server.port())
+            .tls(config)
+            .connect()
+    }
+
+    #[test]
+    fn test_tls_round_trip() {
+        let authority = Authority::new();
+        let server = MockServer::start_tls(Arc::new(server_config(&authority, None)));
+        let config = TlsConfig::new()
+            .root_certificates_pem(&authority.pem())
+            .server_name("localhost");
+        let mut conn = connect(&server, config).unwrap();
+
+        let id = conn.put_default(b"encrypted").unwrap();
+        let mut job = conn.reserve().unwrap().into_job().unwrap();
+        assert_eq!(id, job.id());
+        assert_eq!(b"encrypted", job.body());
+        job.delete().unwrap();
+    }
+
+    #[test]
+    fn test_tls_client_certificate() {
+        let authority = Authority::new();
+        let server =
+            MockServer::start_tls(Arc::new(server_config(&authority, Some(authority.roots()))));
+        let client = authority.issue("worker", ExtendedKeyUsagePurpose::ClientAuth);
+        let config = TlsConfig::new()
+            .root_certificates_pem(&authority.pem())
+            .client_certificate_pem(
+                client.cert.pem().as_bytes(),
+                client.key_pair.serialize_pem().as_bytes(),
+            )
+            .server_name("localhost");
+        let mut conn = connect(&server, config).unwrap();
+
+        assert_eq!("default", conn.using().unwrap());
+    }
+
+    #[test]
+    fn test_tls_untrusted_server() {
+        let server = MockServer::start_tls(Arc::new(server_config(&Authority::new(), None)));
+        let config = TlsConfig::new()
+            .root_certificates_pem(&Authority::new().pem())
+            .server_name("localhost");
+
+        match connect(&server, config) {
+            Err(BeanstalkcError::ConnectionError(msg)) => {
+                assert!(msg.starts_with("TLS:"), "{}", msg)
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_tls_server_name_mismatch() {
+        let authority = Authority::new();
+        let server = MockServer::start_tls(Arc::new(server_config(&authority, None)));
+        let config = TlsConfig::new()
+            .root_certificates_pem(&authority.pem())
+            .server_name("queue.internal");
+
+        assert!(connect(&server, config).is_err());
+    }
+
+    #[test]
+    fn test_tls_handshake_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // Accept the connection but never answer the handshake.
+        let handle = thread::spawn(move || listener.accept().unwrap());
+
+        let result = Beanstalkc::new()
+            .host("127.0.0.1")
+            .port(port)
+            .connection_timeout(Some(Duration::from_millis(200)))
+            .tls(TlsConfig::new().webpki_roots().server_name("localhost"))
+            .connect();
+        match result {
+            Err(BeanstalkcError::ConnectionError(msg)) => {
+                assert!(msg.starts_with("TLS:"), "{}", msg)
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        drop(handle.join().unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tls_unix_socket() {
+        let result = Beanstalkc::unix("/nonexistent.sock")
+            .tls(TlsConfig::new().webpki_roots())
+            .connect();
+        match result {
+            Err(BeanstalkcError::ConnectionError(msg)) => {
+                assert!(msg.contains("not supported"), "{}", msg)
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_tls_invalid_pem() {
+        let server = MockServer::start();
+        let config = TlsConfig::new().root_certificates_pem(b"not a certificate");
+
+        match connect(&server, config) {
+            Err(BeanstalkcError::ConnectionError(msg)) => {
+                assert!(msg.starts_with("TLS:"), "{}", msg)
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use std::time::Duration;
+
+use crate::config::{DEFAULT_CONNECTION_TIMEOUT, DEFAULT_PORT};
+use crate::error::{BeanstalkcError, BeanstalkcResult};
+
+const SCHEME: &str = "beanstalk://";
+
+/// Connection settings parsed from a `beanstalk://host:port?key=value` URL.
+///
+/// Supported query parameters are `timeout` (e.g. `5s`, `500ms`, `2m`, or `none`),
+/// `use` (the tube to use) and `watch` (a comma separated list of tubes to watch).
+#[derive(Debug, PartialEq)]
+pub struct ConnectionUrl {
+    pub host: String,
+    pub port: u16,
+    pub connection_timeout: Option<Duration>,
+    pub tube: Option<String>,
+    pub watch_list: Vec<String>,
+}
+
+impl ConnectionUrl {
+    pub fn parse(url: &str) -> BeanstalkcResult<ConnectionUrl> {
+        let rest = match url.strip_prefix(SCHEME) {
+            Some(rest) => rest,
+            None => return Err(invalid(url, "scheme must be beanstalk://")),
+        };
+
+        let (authority, query) = match rest.find('?') {
+            Some(i) => (&rest[..i], Some(&rest[i + 1..])),
+            None => (rest, None),
+        };
+        let authority = match authority.find('/') {
+            Some(i) if authority[i..] != *"/" => {
+                return Err(invalid(url, "path is not supported"));
+            }
+            Some(i) => &authority[..i],
+            None => authority,
+        };
+        if authority.contains('@') {
+            return Err(invalid(url, "credentials are not supported"));
+        }
+
+        let (host, port) = split_host_port(url, authority)?;
+        let mut parsed = ConnectionUrl {
+            host,
+            port,
+            connection_timeout: DEFAULT_CONNECTION_TIMEOUT,
+            tube: None,
+            watch_list: vec![],
+        };
+
+        for pair in query.unwrap_or("").split('&').filter(|x| !x.is_empty()) {
+            let (key, value) = match pair.find('=') {
+                Some(i) => (&pair[..i], &pair[i + 1..]),
+                None => return Err(invalid(url, &format!("missing value for {}", pair))),
+            };
+            if value.is_empty() {
+                return Err(invalid(url, &format!("missing value for {}", key)));
+            }
+
+            match key {
+                "timeout" => parsed.connection_timeout = parse_timeout(url, value)?,
+                "use" => parsed.tube = Some(value.to_string()),
+                "watch" => {
+                    let tubes: Vec<String> = value.split(',').map(String::from).collect();
+                    if tubes.iter().any(|x| x.is_empty()) {
+                        return Err(invalid(url, "empty tube name in watch list"));
+                    }
+                    parsed.watch_list = tubes;
+                }
+                _ => return Err(invalid(url, &format!("unknown parameter {}", key))),
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+fn split_host_port(url: &str, authority: &str) -> BeanstalkcResult<(String, u16)> {
+    let (host, port) = if let Some(rest) = authority.strip_prefix('[') {
+        // IPv6 literal, e.g. `[::1]:11300`
+        match rest.find(']') {
+            Some(i) => {
+                let port = &rest[i + 1..];
+                if !port.is_empty() && !port.starts_with(':') {
+                    return Err(invalid(url, "malformed IPv6 host"));
+                }
+                (&rest[..i], port.strip_prefix(':'))
+            }
+            None => return Err(invalid(url, "malformed IPv6 host")),
+        }
+    } else {
+        match authority.rfind(':') {
+            Some(i) => (&authority[..i], Some(&authority[i + 1..])),
+            None => (authority, None),
+        }
+    };
+
+    if host.is_empty() {
+        return Err(invalid(url, "missing host"));
+    }
+
+    let port = match port {
+        None => DEFAULT_PORT,
+        Some(p) => p
+            .parse()
+            .map_err(|_| invalid(url, &format!("invalid port {}", p)))?,
+    };
+
+    Ok((host.to_string(), port))
+}
+
+fn parse_timeout(url: &str, value: &str) -> BeanstalkcResult<Option<Duration>> {
+    if value == "none" {
+        return Ok(None);
+    }
+
+    let digits = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(digits);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| invalid(url, &format!("invalid timeout {}", value)))?;
+
+    let timeout = match unit {
+        "ms" => Duration::from_millis(number),
+        "" | "s" => Duration::from_secs(number),
+        "m" => number
+            .checked_mul(60)
+            .map(Duration::from_secs)
+            .ok_or_else(|| invalid(url, &format!("timeout {} out of range", value)))?,
+        _ => return Err(invalid(url, &format!("invalid timeout {}", value))),
+    };
+    Ok(Some(timeout))
+}
+
+fn invalid(url: &str, reason: &str) -> BeanstalkcError {
+    BeanstalkcError::InvalidUrl(format!("{}: {}", url, reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_full_url() {
+        let url =
+            ConnectionUrl::parse("beanstalk://queue:11301?timeout=5s&use=jobs&watch=a,b").unwrap();
+        assert_eq!(
+            ConnectionUrl {
+                host: "queue".to_string(),
+                port: 11301,
+                connection_timeout: Some(Duration::from_secs(5)),
+                tube: Some("jobs".to_string()),
+                watch_list: vec!["a".to_string(), "b".to_string()],
+            },
+            url
+        );
+    }
+
+    #[test]
+    fn test_parse_defaults() {
+        let url = ConnectionUrl::parse("beanstalk://queue/").unwrap();
+        assert_eq!("queue", url.host);
+        assert_eq!(DEFAULT_PORT, url.port);
+        assert_eq!(DEFAULT_CONNECTION_TIMEOUT, url.connection_timeout);
+        assert_eq!(None, url.tube);
+        assert!(url.watch_list.is_empty());
+    }
+
+    #[test]
+    fn test_parse_ipv6_host() {
+        let url = ConnectionUrl::parse("beanstalk://[::1]:11302").unwrap();
+        assert_eq!("::1", url.host);
+        assert_eq!(11302, url.port);
+
+        let url = ConnectionUrl::parse("beanstalk://[::1]").unwrap();
+        assert_eq!(DEFAULT_PORT, url.port);
+    }
+
+    #[test]
+    fn test_parse_timeout() {
+        let timeout = |value: &str| {
+            ConnectionUrl::parse(&format!("beanstalk://queue?timeout={}", value))
+                .map(|url| url.connection_timeout)
+        };
+        assert_eq!(Some(Duration::from_millis(500)), timeout("500ms").unwrap());
+        assert_eq!(Some(Duration::from_secs(7)), timeout("7").unwrap());
+        assert_eq!(Some(Duration::from_secs(120)), timeout("2m").unwrap());
+        assert_eq!(None, timeout("none").unwrap());
+        assert!(timeout("5h").is_err());
+        assert!(timeout("s").is_err());
+        assert!(timeout("-1s").is_err());
+        assert!(timeout("307445734561825861m").is_err());
+    }
+
+    #[test]
+    fn test_parse_malformed_urls() {
+        for url in &[
+            "http://queue:11300",
+            "queue:11300",
+            "beanstalk://",
+            "beanstalk://:11300",
+            "beanstalk://queue:port",
+            "beanstalk://queue:70000",
+            "beanstalk://queue/tube",
+            "beanstalk://user@queue",
+            "beanstalk://[::1",
+            "beanstalk://[::1]x",
+            "beanstalk://queue?use",
+            "beanstalk://queue?use=",
+            "beanstalk://queue?watch=a,,b",
+            "beanstalk://queue?tube=jobs",
+        ] {
+            match ConnectionUrl::parse(url) {
+                Err(BeanstalkcError::InvalidUrl(msg)) => assert!(msg.starts_with(url)),
+                other => panic!("{} parsed as {:?}", url, other),
+            }
+        }
+    }
+}
@@ -0,0 +1,804 @@
+use std::fmt;
+use std::panic;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::command;
+use crate::config::{DEADLINE_SOON_BACKOFF, DEFAULT_POLL_INTERVAL};
+use crate::error::{BeanstalkcError, BeanstalkcResult};
+use crate::heartbeat::Heartbeat;
+use crate::job::{OwnedJob, ReserveOutcome};
+use crate::retry::RetryPolicy;
+use crate::shutdown::ShutdownHandle;
+use crate::Beanstalkc;
+
+type Factory = dyn Fn() -> BeanstalkcResult<Beanstalkc> + Send + Sync;
+
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+// How often a worker thread checks for a shutdown while a handler runs.
+const SHUTDOWN_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// `Action` tells a `Worker` what to do with a job once its `Handler` returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// The job is done: delete it.
+    Ack,
+    /// The job failed: release it with its priority, to be reserved again after
+    /// the given delay.
+    Retry(Duration),
+    /// The job can't be processed: bury it with its priority.
+    Bury,
+    /// The job failed: retry it as told by the worker's `RetryPolicy`, or bury it with
+    /// its priority if that fails.
+    Fail,
+    /// The job isn't done yet: touch it to reset its TTR, and hand it to the handler
+    /// again.
+    Touch,
+}
+
+/// `Handler` processes the jobs reserved by a `Worker`.
+///
+/// It is shared by all the worker threads. Closures taking a `&OwnedJob` and
+/// returning an `Action` are handlers.
+pub trait Handler: Send + Sync + 'static {
+    /// Process a job. Its stats, as of its reservation, are available through
+    /// `OwnedJob::stats`.
+    fn handle(&self, job: &OwnedJob) -> Action;
+}
+
+impl<F> Handler for F
+where
+    F: Fn(&OwnedJob) -> Action + Send + Sync + 'static,
+{
+    fn handle(&self, job: &OwnedJob) -> Action {
+        self(job)
+    }
+}
+
+/// `Worker` runs a `Handler` on the jobs of a set of tubes, in as many threads as
+/// wanted, each with its own connection.
+///
+/// # Example
+///
+/// ```no_run
+/// use beanstalkc::{Action, Beanstalkc, OwnedJob, Worker};
+///
+/// let worker = Worker::new(
+///     || Beanstalkc::new().connect(),
+///     |job: &OwnedJob| match std::str::from_utf8(job.body()) {
+///         Ok(text) => {
+///             println!("{}", text);
+///             Action::Ack
+///         }
+///         Err(_) => Action::Bury,
+///     },
+/// )
+/// .watch(&["emails", "reports"])
+/// .threads(4);
+///
+/// let shutdown = worker.shutdown_handle();
+/// // Call `shutdown.shutdown()` from a signal handler...
+/// worker.run().unwrap();
+/// ```
+pub struct Worker {
+    factory: Arc<Factory>,
+    handler: Arc<dyn Handler>,
+    watch_list: Vec<String>,
+    threads: usize,
+    poll_interval: Duration,
+    shutdown_timeout: Duration,
+    heartbeat: bool,
+    retry_policy: RetryPolicy,
+    shutdown: ShutdownHandle,
+}
+
+impl Worker {
+    /// Create a worker running `handler` on the jobs reserved through connections
+    /// opened with `factory`.
+    pub fn new<F, H>(factory: F, handler: H) -> Worker
+    where
+        F: Fn() -> BeanstalkcResult<Beanstalkc> + Send + Sync + 'static,
+        H: Handler,
+    {
+        Worker {
+            factory: Arc::new(factory),
+            handler: Arc::new(handler),
+            watch_list: vec![],
+            threads: 1,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            heartbeat: false,
+            retry_policy: RetryPolicy::default(),
+            shutdown: ShutdownHandle::new(),
+        }
+    }
+
+    /// Set the tubes to reserve jobs from. Default is the watch list of the
+    /// connections.
+    pub fn watch(mut self, tubes: &[&str]) -> Self {
+        self.watch_list = tubes.iter().map(|x| x.to_string()).collect();
+        self
+    }
+
+    /// Set the number of threads. Default is `1`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `threads` is `0`.
+    pub fn threads(mut self, threads: usize) -> Self {
+        assert!(threads > 0, "worker needs at least one thread");
+        self.threads = threads;
+        self
+    }
+
+    /// Set how long each thread waits for a job before checking for a shutdown
+    /// request again. Default is `1s`.
+    ///
+    /// The interval is rounded up to whole seconds, since `reserve-with-timeout`
+    /// takes seconds: truncating would turn sub-second values into `0s`, which polls
+    /// without waiting and keeps the thread busy while no job is ready.
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = command::whole_seconds(interval);
+        self
+    }
+
+    /// Set how long the jobs being handled when a shutdown is requested may take to
+    /// complete. Jobs still running then are released, and their handlers' actions
+    /// are ignored. Default is `30s`.
+    ///
+    /// Such handlers are not interrupted: they keep running in the background while
+    /// their released jobs may be reserved by another worker, so they should be safe
+    /// to run twice.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Touch the jobs periodically while their handler runs, based on their TTR, so
+    /// that slow jobs are not handed to another worker in the meantime. Default is
+    /// `false`.
+    ///
+    /// A job whose touch fails with `NOT_FOUND`, e.g. since it was handed to another
+    /// worker anyway, is given up: its handler keeps running in the background, and
+    /// its action is ignored.
+    pub fn heartbeat(mut self, enabled: bool) -> Self {
+        self.heartbeat = enabled;
+        self
+    }
+
+    /// Set the policy retrying the jobs whose handler returns `Action::Fail`. Default
+    /// is `RetryPolicy::default()`, with an exponential backoff from `1s`.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Return a handle that stops the worker threads gracefully: they stop
+    /// reserving jobs, wait for the jobs being handled up to the shutdown timeout,
+    /// and close their connections.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Run the worker threads until a shutdown is requested, or one of them fails.
+    /// Return the first error met, after all the threads stopped.
+    pub fn run(self) -> BeanstalkcResult<()> {
+        let worker = Arc::new(self);
+        let threads: Vec<_> = (0..worker.threads)
+            .map(|_| {
+                let worker = worker.clone();
+                thread::spawn(move || {
+                    let _stop = StopOnExit(worker.shutdown.clone());
+                    worker.consume()
+                })
+            })
+            .collect();
+
+        let results: Vec<_> = threads.into_iter().map(|x| x.join()).collect();
+        let mut result = Ok(());
+        for thread_result in results {
+            match thread_result {
+                Ok(thread_result) => result = result.and(thread_result),
+                Err(panic) => panic::resume_unwind(panic),
+            }
+        }
+        result
+    }
+
+    /// Reserve and handle jobs until a shutdown is requested.
+    fn consume(&self) -> BeanstalkcResult<()> {
+        let mut conn = (self.factory)()?;
+        if !self.watch_list.is_empty() {
+            for tube in &self.watch_list {
+                conn.watch(tube)?;
+            }
+            for tube in conn.watched_tubes() {
+                if !self.watch_list.contains(&tube) {
+                    conn.ignore(&tube)?;
+                }
+            }
+        }
+        let mut executor = Executor::new(self.handler.clone());
+
+        while !self.shutdown.is_shutdown() {
+            let job = match conn.reserve_with_timeout(self.poll_interval)? {
+                ReserveOutcome::Job(job) => job.detach_with_stats()?,
+                ReserveOutcome::TimedOut => continue,
+                ReserveOutcome::DeadlineSoon => {
+                    thread::sleep(DEADLINE_SOON_BACKOFF);
+                    continue;
+                }
+            };
+            match self.process(&mut conn, &mut executor, job) {
+                // The job TTR expired, and it was reserved by another worker since.
+                Err(BeanstalkcError::NotFound) => {}
+                result => result?,
+            }
+        }
+        // Dropping the connection sends `quit`.
+        Ok(())
+    }
+
+    /// Run the handler on a job, and act on the job as told. Release the job if the
+    /// handler is still running when the shutdown timeout expires.
+    fn process(
+        &self,
+        conn: &mut Beanstalkc,
+        executor: &mut Executor,
+        job: OwnedJob,
+    ) -> BeanstalkcResult<()> {
+        let priority = job.stats().map_or(0, |stats| stats.pri);
+        let mut heartbeat = match job.stats() {
+            Some(stats) if self.heartbeat => Some(Heartbeat::new(stats)),
+            _ => None,
+        };
+        // Set once a shutdown is requested, for every call of the handler to come.
+        let mut deadline = None;
+        let job = Arc::new(job);
+        loop {
+            let action = match self.handle(conn, executor, &job, &mut heartbeat, &mut deadline)? {
+                Some(action) => action,
+                None => return conn.release(job.id(), priority, Duration::from_secs(0)),
+            };
+            match action {
+                Action::Ack => return conn.delete(job.id()),
+                Action::Retry(delay) => return conn.release(job.id(), priority, delay),
+                Action::Bury => return conn.bury(job.id(), priority),
+                Action::Fail => {
+                    return match self.retry_policy.apply(conn, job.id(), job.body()) {
+                        Ok(_) => Ok(()),
+                        // Keep a job which could not be retried, e.g. too big to be
+                        // dead-lettered, from coming back to every worker.
+                        Err(_) => conn.bury(job.id(), priority),
+                    };
+                }
+                Action::Touch => {
+                    conn.touch(job.id())?;
+                    if let Some(heartbeat) = heartbeat.as_mut() {
+                        heartbeat.touched();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Run the handler on the executor, so that it can be given up on when the
+    /// shutdown deadline passes, in which case `None` is returned. Meanwhile, touch
+    /// the job as scheduled by the heartbeat, if any.
+    fn handle(
+        &self,
+        conn: &mut Beanstalkc,
+        executor: &mut Executor,
+        job: &Arc<OwnedJob>,
+        heartbeat: &mut Option<Heartbeat>,
+        deadline: &mut Option<Instant>,
+    ) -> BeanstalkcResult<Option<Action>> {
+        if self.expired(deadline) {
+            return Ok(None);
+        }
+        executor.start(job.clone());
+
+        loop {
+            let mut wake = deadline.unwrap_or_else(|| Instant::now() + SHUTDOWN_CHECK_INTERVAL);
+            if let Some(heartbeat) = heartbeat {
+                wake = wake.min(heartbeat.next_touch());
+            }
+            if let Some(action) = executor.wait(wake) {
+                return Ok(Some(action));
+            }
+
+            let now = Instant::now();
+            if let Some(heartbeat) = heartbeat.as_mut().filter(|x| x.next_touch() <= now) {
+                if let Err(e) = conn.touch(job.id()) {
+                    // Leave the handler running on its own, so that its action is not
+                    // taken for the one of the next job.
+                    *executor = Executor::new(self.handler.clone());
+                    return Err(e);
+                }
+                heartbeat.touched();
+            }
+            if self.expired(deadline) {
+                // The executor is left running, its action being ignored.
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Start the shutdown deadline once a shutdown is requested, and return whether
+    /// it passed.
+    fn expired(&self, deadline: &mut Option<Instant>) -> bool {
+        let now = Instant::now();
+        if deadline.is_none() && self.shutdown.is_shutdown() {
+            *deadline = Some(now + self.shutdown_timeout);
+        }
+        deadline.is_some_and(|deadline| now >= deadline)
+    }
+}
+
+/// `Executor` runs the handler of a worker thread on a thread of its own, reused
+/// from job to job, so that the worker thread stays free to touch the job and to
+/// give up on it.
+struct Executor {
+    jobs: Sender<Arc<OwnedJob>>,
+    actions: Receiver<Action>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Executor {
+    fn new(handler: Arc<dyn Handler>) -> Executor {
+        let (jobs, pending) = mpsc::channel::<Arc<OwnedJob>>();
+        let (done, actions) = mpsc::channel();
+        // The thread exits once the executor is dropped, after its current job.
+        let thread = thread::spawn(move || {
+            for job in pending {
+                if done.send(handler.handle(&job)).is_err() {
+                    break;
+                }
+            }
+        });
+        Executor {
+            jobs,
+            actions,
+            thread: Some(thread),
+        }
+    }
+
+    /// Hand a job to the handler.
+    fn start(&mut self, job: Arc<OwnedJob>) {
+        if self.jobs.send(job).is_err() {
+            self.join();
+        }
+    }
+
+    /// Return the action of the handler, unless it is still running at `wake`.
+    fn wait(&mut self, wake: Instant) -> Option<Action> {
+        match self
+            .actions
+            .recv_timeout(wake.saturating_duration_since(Instant::now()))
+        {
+            Ok(action) => Some(action),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => self.join(),
+        }
+    }
+
+    /// Propagate the panic of the handler, which is the only way its thread stops
+    /// while the executor is in use.
+    fn join(&mut self) -> ! {
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Err(panic)) => panic::resume_unwind(panic),
+            _ => unreachable!("handler thread exited without an action"),
+        }
+    }
+}
+
+impl fmt::Debug for Worker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Worker")
+            .field("watch_list", &self.watch_list)
+            .field("threads", &self.threads)
+            .field("poll_interval", &self.poll_interval)
+            .field("shutdown_timeout", &self.shutdown_timeout)
+            .field("heartbeat", &self.heartbeat)
+            .field("retry_policy", &self.retry_policy)
+            .field("shutdown", &self.shutdown)
+            .finish()
+    }
+}
+
+/// Stop the other worker threads when one of them returns or panics.
+struct StopOnExit(ShutdownHandle);
+
+impl Drop for StopOnExit {
+    fn drop(&mut self) {
+        self.0.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::{Mutex, OnceLock};
+
+    use super::*;
+    use crate::dead_letter::DeadLetter;
+    use crate::mock::MockServer;
+    use crate::stats::JobState;
+
+    /// The shutdown handle of a worker, for handlers built before the worker.
+    #[derive(Clone, Default)]
+    struct WorkerShutdown(Arc<OnceLock<ShutdownHandle>>);
+
+    impl WorkerShutdown {
+        fn bind(&self, worker: &Worker) {
+            self.0.set(worker.shutdown_handle()).unwrap();
+        }
+
+        fn shutdown(&self) {
+            self.0.get().unwrap().shutdown();
+        }
+    }
+
+    fn worker<H: Handler>(server: &MockServer, handler: H) -> Worker {
+        let port = server.port();
+        Worker::new(move || Beanstalkc::new().port(port).connect(), handler)
+            .poll_interval(Duration::from_secs(0))
+    }
+
+    #[test]
+    fn test_poll_interval_rounds_up() {
+        let server = MockServer::start();
+        let worker = worker(&server, |_: &OwnedJob| Action::Ack);
+        assert_eq!(
+            Duration::from_secs(1),
+            worker
+                .poll_interval(Duration::from_millis(200))
+                .poll_interval
+        );
+    }
+
+    #[test]
+    fn test_ack_jobs_from_watched_tubes() {
+        let server = MockServer::start();
+        let mut producer = server.connect();
+        producer.put_default(b"ignored").unwrap();
+        producer.use_tube("jobs").unwrap();
+        for i in 0..10 {
+            producer
+                .put_default(format!("job-{}", i).as_bytes())
+                .unwrap();
+        }
+
+        let seen = Arc::new(Mutex::new(vec![]));
+        let shutdown = WorkerShutdown::default();
+        let handler = {
+            let (seen, shutdown) = (seen.clone(), shutdown.clone());
+            move |job: &OwnedJob| {
+                let mut seen = seen.lock().unwrap();
+                seen.push(job.body().to_vec());
+                if seen.len() == 10 {
+                    shutdown.shutdown();
+                }
+                Action::Ack
+            }
+        };
+        let worker = worker(&server, handler).watch(&["jobs"]).threads(3);
+        shutdown.bind(&worker);
+        worker.run().unwrap();
+
+        let mut seen = seen.lock().unwrap().clone();
+        seen.sort();
+        let expected: Vec<_> = (0..10).map(|i| format!("job-{}", i).into_bytes()).collect();
+        assert_eq!(expected, seen);
+        assert!(producer.peek_ready().is_err());
+        producer.use_tube("default").unwrap();
+        assert_eq!(b"ignored", producer.peek_ready().unwrap().body());
+    }
+
+    #[test]
+    fn test_retry_bury_and_touch() {
+        let server = MockServer::start();
+        let mut producer = server.connect();
+        // Jobs are reserved by priority, the retried one coming last.
+        let ttr = Duration::from_secs(60);
+        let touched = producer
+            .put(b"touch", 7, Duration::from_secs(0), ttr)
+            .unwrap();
+        let buried = producer
+            .put(b"bury", 8, Duration::from_secs(0), ttr)
+            .unwrap();
+        let retried = producer
+            .put(b"retry", 9, Duration::from_secs(0), ttr)
+            .unwrap();
+
+        let calls = Arc::new(Mutex::new(HashSet::new()));
+        let shutdown = WorkerShutdown::default();
+        let handler = {
+            let (calls, shutdown) = (calls.clone(), shutdown.clone());
+            move |job: &OwnedJob| {
+                let first = calls.lock().unwrap().insert(job.body().to_vec());
+                match (job.body(), first) {
+                    (b"retry", true) => Action::Retry(Duration::from_secs(0)),
+                    (b"bury", _) => Action::Bury,
+                    (b"touch", true) => Action::Touch,
+                    _ => {
+                        if job.body() == b"retry" {
+                            shutdown.shutdown();
+                        }
+                        Action::Ack
+                    }
+                }
+            }
+        };
+        let worker = worker(&server, handler);
+        shutdown.bind(&worker);
+        worker.run().unwrap();
+
+        let stats = producer.stats_job(buried).unwrap();
+        assert_eq!(JobState::Buried, stats.state);
+        assert_eq!(8, stats.pri);
+        assert!(producer.peek(touched).is_err());
+        assert!(producer.peek(retried).is_err());
+    }
+
+    #[test]
+    fn test_retry_keeps_priority() {
+        let server = MockServer::start();
+        let mut producer = server.connect();
+        let id = producer
+            .put(b"job", 7, Duration::from_secs(0), Duration::from_secs(60))
+            .unwrap();
+
+        let worker = worker(&server, |_: &OwnedJob| {
+            Action::Retry(Duration::from_secs(60))
+        });
+        let shutdown = worker.shutdown_handle();
+        let running = thread::spawn(move || worker.run());
+        while producer.stats_job(id).unwrap().state != JobState::Delayed {
+            thread::sleep(Duration::from_millis(10));
+        }
+        shutdown.shutdown();
+        running.join().unwrap().unwrap();
+
+        let stats = producer.stats_job(id).unwrap();
+        assert_eq!(7, stats.pri);
+        assert_eq!(1, stats.releases);
+    }
+
+    #[test]
+    fn test_fail_applies_retry_policy() {
+        let server = MockServer::start();
+        let mut producer = server.connect();
+        let id = producer.put_default(b"job").unwrap();
+
+        let attempts = Arc::new(Mutex::new(0));
+        let shutdown = WorkerShutdown::default();
+        let handler = {
+            let (attempts, shutdown) = (attempts.clone(), shutdown.clone());
+            move |_: &OwnedJob| {
+                let mut attempts = attempts.lock().unwrap();
+                *attempts += 1;
+                if *attempts == 3 {
+                    shutdown.shutdown();
+                }
+                Action::Fail
+            }
+        };
+        let policy = RetryPolicy::fixed(Duration::from_secs(0)).max_attempts(3);
+        let worker = worker(&server, handler).retry_policy(policy);
+        shutdown.bind(&worker);
+        worker.run().unwrap();
+
+        let stats = producer.stats_job(id).unwrap();
+        assert_eq!(JobState::Buried, stats.state);
+        assert_eq!(2, stats.releases);
+        assert_eq!(3, stats.reserves);
+    }
+
+    #[test]
+    fn test_fail_buries_job_too_big_to_dead_letter() {
+        let server = MockServer::start();
+        server.max_job_size(65535);
+        let mut producer = server.connect();
+        let big = producer
+            .put(
+                &vec![b'x'; 65500],
+                7,
+                Duration::from_secs(0),
+                Duration::from_secs(60),
+            )
+            .unwrap();
+        let small = producer.put_default(b"small").unwrap();
+
+        let shutdown = WorkerShutdown::default();
+        let handler = {
+            let shutdown = shutdown.clone();
+            move |job: &OwnedJob| {
+                if job.body() == b"small" {
+                    shutdown.shutdown();
+                    return Action::Ack;
+                }
+                Action::Fail
+            }
+        };
+        let policy = RetryPolicy::fixed(Duration::from_secs(0))
+            .max_attempts(1)
+            .dead_letter_when_exhausted(DeadLetter::new());
+        let worker = worker(&server, handler).retry_policy(policy);
+        shutdown.bind(&worker);
+        worker.run().unwrap();
+
+        let stats = producer.stats_job(big).unwrap();
+        assert_eq!(JobState::Buried, stats.state);
+        assert_eq!(7, stats.pri);
+        assert!(producer.peek(small).is_err());
+        assert!(producer.stats_tube("default.dead").is_err());
+    }
+
+    #[test]
+    fn test_shutdown_lets_job_finish() {
+        let server = MockServer::start();
+        let mut producer = server.connect();
+        let first = producer.put_default(b"first").unwrap();
+        let second = producer.put_default(b"second").unwrap();
+
+        let shutdown = WorkerShutdown::default();
+        let handler = {
+            let shutdown = shutdown.clone();
+            move |_: &OwnedJob| {
+                shutdown.shutdown();
+                thread::sleep(Duration::from_millis(300));
+                Action::Ack
+            }
+        };
+        let worker = worker(&server, handler).shutdown_timeout(Duration::from_secs(5));
+        shutdown.bind(&worker);
+        worker.run().unwrap();
+
+        assert!(producer.peek(first).is_err());
+        assert_eq!(JobState::Ready, producer.stats_job(second).unwrap().state);
+    }
+
+    #[test]
+    fn test_shutdown_timeout_releases_job() {
+        let server = MockServer::start();
+        let mut producer = server.connect();
+        let id = producer
+            .put(b"job", 7, Duration::from_secs(0), Duration::from_secs(60))
+            .unwrap();
+
+        let shutdown = WorkerShutdown::default();
+        let handler = {
+            let shutdown = shutdown.clone();
+            move |_: &OwnedJob| {
+                shutdown.shutdown();
+                thread::sleep(Duration::from_secs(2));
+                Action::Ack
+            }
+        };
+        let worker = worker(&server, handler).shutdown_timeout(Duration::from_millis(100));
+        shutdown.bind(&worker);
+        let started = Instant::now();
+        worker.run().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        let stats = producer.stats_job(id).unwrap();
+        assert_eq!(JobState::Ready, stats.state);
+        assert_eq!(7, stats.pri);
+        assert_eq!(1, stats.releases);
+    }
+
+    #[test]
+    fn test_shutdown_timeout_spans_touches() {
+        let server = MockServer::start();
+        let mut producer = server.connect();
+        let id = producer.put_default(b"job").unwrap();
+
+        let shutdown = WorkerShutdown::default();
+        let handler = {
+            let shutdown = shutdown.clone();
+            move |_: &OwnedJob| {
+                shutdown.shutdown();
+                thread::sleep(Duration::from_millis(50));
+                Action::Touch
+            }
+        };
+        let worker = worker(&server, handler).shutdown_timeout(Duration::from_millis(300));
+        shutdown.bind(&worker);
+        let started = Instant::now();
+        worker.run().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        let stats = producer.stats_job(id).unwrap();
+        assert_eq!(JobState::Ready, stats.state);
+        assert_eq!(1, stats.releases);
+    }
+
+    #[test]
+    fn test_heartbeat_keeps_job_reserved() {
+        let server = MockServer::start();
+        let mut producer = server.connect();
+        let id = producer
+            .put(b"job", 0, Duration::from_secs(0), Duration::from_secs(1))
+            .unwrap();
+
+        let shutdown = WorkerShutdown::default();
+        let handler = {
+            let shutdown = shutdown.clone();
+            move |_: &OwnedJob| {
+                thread::sleep(Duration::from_millis(2500));
+                shutdown.shutdown();
+                Action::Retry(Duration::from_secs(60))
+            }
+        };
+        let worker = worker(&server, handler).heartbeat(true);
+        shutdown.bind(&worker);
+        worker.run().unwrap();
+
+        let stats = producer.stats_job(id).unwrap();
+        assert_eq!(JobState::Delayed, stats.state);
+        assert_eq!(0, stats.timeouts);
+        assert_eq!(1, stats.releases);
+    }
+
+    #[test]
+    fn test_failed_touch_gives_up_job() {
+        let server = MockServer::start();
+        let mut producer = server.connect();
+        let ttr = Duration::from_secs(4);
+        let slow = producer
+            .put(b"slow", 0, Duration::from_secs(0), ttr)
+            .unwrap();
+        let fast = producer
+            .put(b"fast", 1, Duration::from_secs(0), ttr)
+            .unwrap();
+
+        let shutdown = WorkerShutdown::default();
+        let handler = {
+            let shutdown = shutdown.clone();
+            move |job: &OwnedJob| {
+                if job.body() == b"slow" {
+                    // Still running when the heartbeat touch fails.
+                    thread::sleep(Duration::from_millis(2500));
+                    return Action::Ack;
+                }
+                shutdown.shutdown();
+                Action::Bury
+            }
+        };
+        server.reject_touches(true);
+        let worker = worker(&server, handler).heartbeat(true);
+        shutdown.bind(&worker);
+        worker.run().unwrap();
+
+        assert_eq!(JobState::Buried, producer.stats_job(fast).unwrap().state);
+        assert_eq!(JobState::Ready, producer.stats_job(slow).unwrap().state);
+    }
+
+    #[test]
+    #[should_panic(expected = "handler failed")]
+    fn test_handler_panic() {
+        let server = MockServer::start();
+        server.connect().put_default(b"job").unwrap();
+
+        let worker = worker(&server, |_: &OwnedJob| -> Action {
+            panic!("handler failed")
+        });
+        let _ = worker.run();
+    }
+
+    #[test]
+    fn test_connection_error() {
+        let worker = Worker::new(
+            || Beanstalkc::new().port(1).connect(),
+            |_: &OwnedJob| Action::Ack,
+        )
+        .threads(2);
+        assert!(matches!(
+            worker.run(),
+            Err(BeanstalkcError::ConnectionError(_))
+        ));
+    }
+}